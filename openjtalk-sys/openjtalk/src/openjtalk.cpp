@@ -4,6 +4,9 @@
 #include <mecab.h>
 #include <njd.h>
 
+#include <cerrno>
+#include <climits>
+#include <cstdlib>
 #include <cstring>
 #include <stdexcept>
 #include <string>
@@ -37,27 +40,303 @@ class OpenJTalk {
   ~OpenJTalk() { clear(); }
 
   char **extract_fullcontext(std::string text, int *size);
+  char **run_mecab(std::string text, int *size);
+  char **run_frontend(std::string text, int *size);
+  char **run_pronunciation(std::string text, int *size);
+  char **complete_frontend(const char **features, int features_size, int *size);
+  char **run_frontend_nbest(std::string text, int n, int *size);
+  char **make_label(const char **features, int features_size, int *size);
 
   void load(const std::string& dn_mecab);
   void clear();
+
+ private:
+  void run_njd_pipeline();
+  void run_njd_after_pronunciation();
+  std::vector<std::string> njd_features();
+  bool load_njd_features(const char **features, int features_size);
+  char **take_labels(int *size);
 };
+
+char **to_c_strings(const std::vector<std::string>& strings, int *size) {
+  *size = strings.size();
+  char** result = (char **)malloc(strings.size() * sizeof(char *));
+  for (size_t i = 0; i < strings.size(); i++) {
+    result[i] = strdup(strings[i].c_str());
+  }
+  return result;
+}
+
+std::vector<std::string> split_feature(const std::string& feature) {
+  std::vector<std::string> fields;
+  size_t start = 0;
+  size_t end;
+  while ((end = feature.find(',', start)) != std::string::npos) {
+    fields.push_back(feature.substr(start, end - start));
+    start = end + 1;
+  }
+  fields.push_back(feature.substr(start));
+  return fields;
+}
+
+// 例外を投げずに整数を読む。数字以外を含む場合や範囲外の場合は false を返す
+bool parse_int(const std::string& field, int *value) {
+  if (field.empty()) {
+    return false;
+  }
+  char *end;
+  errno = 0;
+  long result = std::strtol(field.c_str(), &end, 10);
+  if (*end != '\0' || errno == ERANGE || result < INT_MIN || result > INT_MAX) {
+    return false;
+  }
+  *value = (int)result;
+  return true;
+}
 }
 
 namespace {
 char **OpenJTalk::extract_fullcontext(std::string text, int *size) {
+  char buff[8192];
+  text2mecab(buff, text.c_str());
+  Mecab_analysis(&mecab, buff);
+  mecab2njd(&njd, Mecab_get_feature(&mecab), Mecab_get_size(&mecab));
+  run_njd_pipeline();
+  njd2jpcommon(&jpcommon, &njd);
+  JPCommon_make_label(&jpcommon);
+
+  char **labels = take_labels(size);
+
+  JPCommon_refresh(&jpcommon);
+  NJD_refresh(&njd);
+  Mecab_refresh(&mecab);
+
+  return labels;
+}
+
+// MeCab の形態素を「ノードの種類,表層形,素性」の形式の文字列で返す
+char **OpenJTalk::run_mecab(std::string text, int *size) {
+  char buff[8192];
+  text2mecab(buff, text.c_str());
+
+  MeCab::Tagger *tagger = reinterpret_cast<MeCab::Tagger *>(mecab.tagger);
+  MeCab::Lattice *lattice = reinterpret_cast<MeCab::Lattice *>(mecab.lattice);
+  lattice->set_sentence(buff);
+
+  std::vector<std::string> result;
+  if (tagger->parse(lattice)) {
+    for (const MeCab::Node *node = lattice->bos_node(); node; node = node->next) {
+      if (node->stat != MECAB_BOS_NODE && node->stat != MECAB_EOS_NODE) {
+        std::string morpheme = std::to_string(node->stat);
+        morpheme += ",";
+        morpheme += std::string(node->surface, node->length);
+        morpheme += ",";
+        morpheme += node->feature;
+        result.push_back(morpheme);
+      }
+    }
+  }
+  lattice->clear();
+
+  return to_c_strings(result, size);
+}
+
+char **OpenJTalk::run_frontend(std::string text, int *size) {
+  char buff[8192];
+  text2mecab(buff, text.c_str());
+  Mecab_analysis(&mecab, buff);
+  mecab2njd(&njd, Mecab_get_feature(&mecab), Mecab_get_size(&mecab));
+  run_njd_pipeline();
+
+  char **features = to_c_strings(njd_features(), size);
+
+  NJD_refresh(&njd);
+  Mecab_refresh(&mecab);
+
+  return features;
+}
+
+// 発音を付けた直後の NJD の素性を返す。数字の読みやアクセント句はまだ決まっていない
+char **OpenJTalk::run_pronunciation(std::string text, int *size) {
   char buff[8192];
   text2mecab(buff, text.c_str());
   Mecab_analysis(&mecab, buff);
   mecab2njd(&njd, Mecab_get_feature(&mecab), Mecab_get_size(&mecab));
   njd_set_pronunciation(&njd);
+
+  char **features = to_c_strings(njd_features(), size);
+
+  NJD_refresh(&njd);
+  Mecab_refresh(&mecab);
+
+  return features;
+}
+
+// run_pronunciation の素性に、発音より後の処理をかけた素性を返す
+// 素性の形式が正しくない場合は NULL を返す
+char **OpenJTalk::complete_frontend(const char **features, int features_size, int *size) {
+  if (!load_njd_features(features, features_size)) {
+    *size = 0;
+    return NULL;
+  }
+  run_njd_after_pronunciation();
+
+  char **result = to_c_strings(njd_features(), size);
+
+  NJD_refresh(&njd);
+
+  return result;
+}
+
+// 候補ごとの NJD の素性を順に並べ、候補の区切りには空文字列を置く
+char **OpenJTalk::run_frontend_nbest(std::string text, int n, int *size) {
+  char buff[8192];
+  text2mecab(buff, text.c_str());
+
+  MeCab::Tagger *tagger = reinterpret_cast<MeCab::Tagger *>(mecab.tagger);
+  MeCab::Lattice *lattice = reinterpret_cast<MeCab::Lattice *>(mecab.lattice);
+  lattice->set_request_type(MECAB_NBEST);
+  lattice->set_sentence(buff);
+
+  std::vector<std::string> result;
+  if (tagger->parse(lattice)) {
+    for (int i = 0; i < n; i++) {
+      std::vector<std::string> mecab_features;
+      for (const MeCab::Node *node = lattice->bos_node(); node; node = node->next) {
+        if (node->stat != MECAB_BOS_NODE && node->stat != MECAB_EOS_NODE) {
+          std::string feature(node->surface, node->length);
+          feature += ",";
+          feature += node->feature;
+          mecab_features.push_back(feature);
+        }
+      }
+      std::vector<char *> mecab_feature_ptrs;
+      for (auto& feature : mecab_features) {
+        mecab_feature_ptrs.push_back(&feature[0]);
+      }
+
+      mecab2njd(&njd, mecab_feature_ptrs.data(), mecab_feature_ptrs.size());
+      run_njd_pipeline();
+      if (i > 0) {
+        result.push_back("");
+      }
+      for (const auto& feature : njd_features()) {
+        result.push_back(feature);
+      }
+      NJD_refresh(&njd);
+
+      if (!lattice->next()) {
+        break;
+      }
+    }
+  }
+  lattice->set_request_type(MECAB_ONE_BEST);
+  lattice->clear();
+
+  return to_c_strings(result, size);
+}
+
+// 素性の形式が正しくない場合は NULL を返す
+char **OpenJTalk::make_label(const char **features, int features_size, int *size) {
+  if (!load_njd_features(features, features_size)) {
+    *size = 0;
+    return NULL;
+  }
+  njd2jpcommon(&jpcommon, &njd);
+  JPCommon_make_label(&jpcommon);
+
+  char **labels = take_labels(size);
+
+  JPCommon_refresh(&jpcommon);
+  NJD_refresh(&njd);
+
+  return labels;
+}
+
+void OpenJTalk::run_njd_pipeline() {
+  njd_set_pronunciation(&njd);
+  run_njd_after_pronunciation();
+}
+
+void OpenJTalk::run_njd_after_pronunciation() {
   njd_set_digit(&njd);
   njd_set_accent_phrase(&njd);
   njd_set_accent_type(&njd);
   njd_set_unvoiced_vowel(&njd);
   njd_set_long_vowel(&njd);
-  njd2jpcommon(&jpcommon, &njd);
-  JPCommon_make_label(&jpcommon);
+}
 
+// NJD のノードを
+// 表層形,品詞,品詞細分類1,品詞細分類2,品詞細分類3,活用型,活用形,原形,読み,発音,アクセント型,モーラ数,アクセント結合規則,連結フラグ
+// の形式の文字列に変換する
+std::vector<std::string> OpenJTalk::njd_features() {
+  std::vector<std::string> features;
+  for (NJDNode *node = njd.head; node != NULL; node = node->next) {
+    std::string feature;
+    feature += NJDNode_get_string(node);
+    feature += ",";
+    feature += NJDNode_get_pos(node);
+    feature += ",";
+    feature += NJDNode_get_pos_group1(node);
+    feature += ",";
+    feature += NJDNode_get_pos_group2(node);
+    feature += ",";
+    feature += NJDNode_get_pos_group3(node);
+    feature += ",";
+    feature += NJDNode_get_ctype(node);
+    feature += ",";
+    feature += NJDNode_get_cform(node);
+    feature += ",";
+    feature += NJDNode_get_orig(node);
+    feature += ",";
+    feature += NJDNode_get_read(node);
+    feature += ",";
+    feature += NJDNode_get_pron(node);
+    feature += ",";
+    feature += std::to_string(NJDNode_get_acc(node));
+    feature += ",";
+    feature += std::to_string(NJDNode_get_mora_size(node));
+    feature += ",";
+    feature += NJDNode_get_chain_rule(node);
+    feature += ",";
+    feature += std::to_string(NJDNode_get_chain_flag(node));
+    features.push_back(feature);
+  }
+  return features;
+}
+
+// 一つでも形式の正しくない素性がある場合は、読み込んだノードを捨てて false を返す
+bool OpenJTalk::load_njd_features(const char **features, int features_size) {
+  for (int i = 0; i < features_size; i++) {
+    std::vector<std::string> fields = split_feature(features[i]);
+    int acc, mora_size, chain_flag;
+    if (fields.size() != 14 || !parse_int(fields[10], &acc) || !parse_int(fields[11], &mora_size) ||
+        !parse_int(fields[13], &chain_flag)) {
+      NJD_refresh(&njd);
+      return false;
+    }
+    NJDNode *node = (NJDNode *)calloc(1, sizeof(NJDNode));
+    NJDNode_initialize(node);
+    NJDNode_set_string(node, fields[0].c_str());
+    NJDNode_set_pos(node, fields[1].c_str());
+    NJDNode_set_pos_group1(node, fields[2].c_str());
+    NJDNode_set_pos_group2(node, fields[3].c_str());
+    NJDNode_set_pos_group3(node, fields[4].c_str());
+    NJDNode_set_ctype(node, fields[5].c_str());
+    NJDNode_set_cform(node, fields[6].c_str());
+    NJDNode_set_orig(node, fields[7].c_str());
+    NJDNode_set_read(node, fields[8].c_str());
+    NJDNode_set_pron(node, fields[9].c_str());
+    NJDNode_set_acc(node, acc);
+    NJDNode_set_mora_size(node, mora_size);
+    NJDNode_set_chain_rule(node, fields[12].c_str());
+    NJDNode_set_chain_flag(node, chain_flag);
+    NJD_push_node(&njd, node);
+  }
+  return true;
+}
+
+char **OpenJTalk::take_labels(int *size) {
   int label_size = JPCommon_get_label_size(&jpcommon);
   char** label_feature = JPCommon_get_label_feature(&jpcommon);
 
@@ -67,11 +346,6 @@ char **OpenJTalk::extract_fullcontext(std::string text, int *size) {
   for (int i = 0; i < label_size; i++) {
     labels[i] = strdup(label_feature[i]);
   }
-
-  JPCommon_refresh(&jpcommon);
-  NJD_refresh(&njd);
-  Mecab_refresh(&mecab);
-
   return labels;
 }
 
@@ -105,6 +379,64 @@ extern "C" char **OpenJTalk_extract_fullcontext(void *openjtalk, const char *tex
   return labels;
 }
 
+extern "C" char **OpenJTalk_run_mecab(void *openjtalk, const char *text, size_t *size) {
+  int morphemes_size;
+  char **morphemes = ((OpenJTalk *)openjtalk)->run_mecab(text, &morphemes_size);
+  *size = morphemes_size;
+  return morphemes;
+}
+
+extern "C" char **OpenJTalk_run_frontend(void *openjtalk, const char *text, size_t *size) {
+  int features_size;
+  char **features = ((OpenJTalk *)openjtalk)->run_frontend(text, &features_size);
+  *size = features_size;
+  return features;
+}
+
+extern "C" char **OpenJTalk_run_frontend_nbest(void *openjtalk, const char *text, size_t n, size_t *size) {
+  int features_size;
+  char **features = ((OpenJTalk *)openjtalk)->run_frontend_nbest(text, n, &features_size);
+  *size = features_size;
+  return features;
+}
+
+extern "C" char **OpenJTalk_run_pronunciation(void *openjtalk, const char *text, size_t *size) {
+  int features_size;
+  char **features = ((OpenJTalk *)openjtalk)->run_pronunciation(text, &features_size);
+  *size = features_size;
+  return features;
+}
+
+// 素性の形式が正しくない場合は 1 を返す。C++ の例外はここで止める
+extern "C" int OpenJTalk_complete_frontend(void *openjtalk, const char **features, size_t features_size,
+                                           char ***result, size_t *size) {
+  try {
+    int result_size;
+    *result = ((OpenJTalk *)openjtalk)->complete_frontend(features, features_size, &result_size);
+    *size = result_size;
+    return *result == NULL ? 1 : 0;
+  } catch (...) {
+    *result = NULL;
+    *size = 0;
+    return 1;
+  }
+}
+
+// 素性の形式が正しくない場合は 1 を返す。C++ の例外はここで止める
+extern "C" int OpenJTalk_make_label(void *openjtalk, const char **features, size_t features_size, char ***labels,
+                                    size_t *size) {
+  try {
+    int labels_size;
+    *labels = ((OpenJTalk *)openjtalk)->make_label(features, features_size, &labels_size);
+    *size = labels_size;
+    return *labels == NULL ? 1 : 0;
+  } catch (...) {
+    *labels = NULL;
+    *size = 0;
+    return 1;
+  }
+}
+
 extern "C" int OpenJTalk_load(void *openjtalk, const char *dn_mecab) {
   try {
     ((OpenJTalk *)openjtalk)->load(dn_mecab);
@@ -4,6 +4,14 @@
 
 extern "C" void *OpenJTalk_create();
 extern "C" char **OpenJTalk_extract_fullcontext(void *openjtalk, const char *text, size_t *size);
+extern "C" char **OpenJTalk_run_mecab(void *openjtalk, const char *text, size_t *size);
+extern "C" char **OpenJTalk_run_frontend(void *openjtalk, const char *text, size_t *size);
+extern "C" char **OpenJTalk_run_pronunciation(void *openjtalk, const char *text, size_t *size);
+extern "C" int OpenJTalk_complete_frontend(void *openjtalk, const char **features, size_t features_size,
+                                           char ***result, size_t *size);
+extern "C" char **OpenJTalk_run_frontend_nbest(void *openjtalk, const char *text, size_t n, size_t *size);
+extern "C" int OpenJTalk_make_label(void *openjtalk, const char **features, size_t features_size, char ***labels,
+                                    size_t *size);
 extern "C" int OpenJTalk_load(void *openjtalk, const char *dn_mecab);
 extern "C" void OpenJTalk_clear(void *openjtalk);
 extern "C" void OpenJTalk_delete(void *openjtalk);
@@ -0,0 +1,41 @@
+use openjtalk::OpenJTalk;
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (dic_path, text) = if args.len() > 2 {
+        (PathBuf::from(args[1].clone()), args[2].clone())
+    } else {
+        println!("usage: cargo run --example nbest -- <open_jtalk_dic_path> <text> [n]");
+        std::process::exit(1);
+    };
+    let n = args
+        .get(3)
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(5);
+
+    let ojt = match OpenJTalk::new(&dic_path) {
+        Ok(ojt) => ojt,
+        Err(msg) => {
+            println!("{}", msg);
+            std::process::exit(1);
+        }
+    };
+    println!("OpenJTalk initialized");
+    let candidates = match ojt.run_frontend_nbest(text, n) {
+        Ok(candidates) => candidates,
+        Err(msg) => {
+            println!("{}", msg);
+            std::process::exit(1);
+        }
+    };
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!("candidate {}: {}", i, candidate.pronunciation());
+        for feature in candidate.features.iter() {
+            println!("  {}", feature);
+        }
+    }
+    ojt.delete();
+}
@@ -1,7 +1,12 @@
+pub mod mecab;
+pub mod njd;
+
+pub use mecab::MecabMorpheme;
+pub use njd::{NBestCandidate, NjdFeature};
 use openjtalk_sys::*;
 
 use std::ffi::CString;
-use std::os::raw::c_void;
+use std::os::raw::{c_char, c_void};
 use std::path::Path;
 
 #[derive(Clone, Copy)]
@@ -35,16 +40,142 @@ impl OpenJTalk {
         let text = CString::new(text.as_ref()).unwrap();
         let box_size = Box::new(0);
         let size_ptr = Box::into_raw(box_size);
-        let mut result = Vec::new();
         unsafe {
             let labels_ptr = OpenJTalk_extract_fullcontext(self.ptr, text.as_ptr(), size_ptr);
             let box_size = Box::from_raw(size_ptr);
-            for ptr in std::slice::from_raw_parts(labels_ptr, *box_size as usize) {
-                let c_str = CString::from_raw(*ptr);
-                result.push(c_str.to_str().unwrap().to_string());
+            take_strings(labels_ptr, *box_size as usize)
+        }
+    }
+
+    /// テキストを MeCab で形態素解析した結果を返す
+    pub fn run_mecab<T: AsRef<str>>(&self, text: T) -> Result<Vec<MecabMorpheme>, String> {
+        let text = CString::new(text.as_ref()).unwrap();
+        let box_size = Box::new(0);
+        let size_ptr = Box::into_raw(box_size);
+        let morphemes = unsafe {
+            let morphemes_ptr = OpenJTalk_run_mecab(self.ptr, text.as_ptr(), size_ptr);
+            let box_size = Box::from_raw(size_ptr);
+            take_strings(morphemes_ptr, *box_size as usize)
+        };
+        morphemes.iter().map(|morpheme| morpheme.parse()).collect()
+    }
+
+    /// テキストを解析し、フルコンテキストラベルを作る直前の NJD の素性を返す
+    pub fn run_frontend<T: AsRef<str>>(&self, text: T) -> Result<Vec<NjdFeature>, String> {
+        let text = CString::new(text.as_ref()).unwrap();
+        let box_size = Box::new(0);
+        let size_ptr = Box::into_raw(box_size);
+        let features = unsafe {
+            let features_ptr = OpenJTalk_run_frontend(self.ptr, text.as_ptr(), size_ptr);
+            let box_size = Box::from_raw(size_ptr);
+            take_strings(features_ptr, *box_size as usize)
+        };
+        features.iter().map(|feature| feature.parse()).collect()
+    }
+
+    /// テキストを解析し、発音を付けた直後の NJD の素性を返す
+    ///
+    /// 数字の読み、アクセント句、アクセント型、無声化、長音はまだ処理されていない。
+    /// 素性を書き換えた後に [`OpenJTalk::complete_frontend`] で残りの処理をかける。
+    pub fn run_pronunciation<T: AsRef<str>>(&self, text: T) -> Result<Vec<NjdFeature>, String> {
+        let text = CString::new(text.as_ref()).unwrap();
+        let box_size = Box::new(0);
+        let size_ptr = Box::into_raw(box_size);
+        let features = unsafe {
+            let features_ptr = OpenJTalk_run_pronunciation(self.ptr, text.as_ptr(), size_ptr);
+            let box_size = Box::from_raw(size_ptr);
+            take_strings(features_ptr, *box_size as usize)
+        };
+        features.iter().map(|feature| feature.parse()).collect()
+    }
+
+    /// [`OpenJTalk::run_pronunciation`] の素性に残りの処理をかけ、
+    /// [`OpenJTalk::run_frontend`] と同じ段階の素性を返す
+    pub fn complete_frontend(&self, features: &[NjdFeature]) -> Result<Vec<NjdFeature>, String> {
+        let features = to_cstrings(features)?;
+        let mut feature_ptrs = features
+            .iter()
+            .map(|feature| feature.as_ptr())
+            .collect::<Vec<_>>();
+        let mut result_ptr = std::ptr::null_mut();
+        let mut size = 0;
+        let res = unsafe {
+            OpenJTalk_complete_frontend(
+                self.ptr,
+                feature_ptrs.as_mut_ptr(),
+                feature_ptrs.len() as size_t,
+                &mut result_ptr,
+                &mut size,
+            )
+        };
+        if res != 0 {
+            return Err("OpenJTalk couldn't read the njd features".to_string());
+        }
+        let features = unsafe { take_strings(result_ptr, size as usize) };
+        features.iter().map(|feature| feature.parse()).collect()
+    }
+
+    /// MeCab の N-best 解析により、読みの候補を最大 `n` 個返す
+    ///
+    /// 表層形・発音・アクセント型がすべて同じ候補は一つにまとめる。
+    pub fn run_frontend_nbest<T: AsRef<str>>(
+        &self,
+        text: T,
+        n: usize,
+    ) -> Result<Vec<NBestCandidate>, String> {
+        let text = CString::new(text.as_ref()).unwrap();
+        let box_size = Box::new(0);
+        let size_ptr = Box::into_raw(box_size);
+        let features = unsafe {
+            let features_ptr =
+                OpenJTalk_run_frontend_nbest(self.ptr, text.as_ptr(), n as size_t, size_ptr);
+            let box_size = Box::from_raw(size_ptr);
+            take_strings(features_ptr, *box_size as usize)
+        };
+
+        let mut candidates: Vec<NBestCandidate> = Vec::new();
+        // 候補と候補の間は空文字列で区切られている
+        for candidate_features in features.split(|feature| feature.is_empty()) {
+            let candidate = NBestCandidate::new(
+                candidate_features
+                    .iter()
+                    .map(|feature| feature.parse())
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+            if !candidates
+                .iter()
+                .any(|other| other.is_same_reading(&candidate))
+            {
+                candidates.push(candidate);
             }
         }
-        result
+        Ok(candidates)
+    }
+
+    /// NJD の素性からフルコンテキストラベルを作る
+    ///
+    /// 素性は `,` 区切りで OpenJTalk に渡すため、`,` を含むフィールドはエラーにする。
+    pub fn make_label(&self, features: &[NjdFeature]) -> Result<Vec<String>, String> {
+        let features = to_cstrings(features)?;
+        let mut feature_ptrs = features
+            .iter()
+            .map(|feature| feature.as_ptr())
+            .collect::<Vec<_>>();
+        let mut labels_ptr = std::ptr::null_mut();
+        let mut size = 0;
+        let res = unsafe {
+            OpenJTalk_make_label(
+                self.ptr,
+                feature_ptrs.as_mut_ptr(),
+                feature_ptrs.len() as size_t,
+                &mut labels_ptr,
+                &mut size,
+            )
+        };
+        if res != 0 {
+            return Err("OpenJTalk couldn't read the njd features".to_string());
+        }
+        Ok(unsafe { take_strings(labels_ptr, size as usize) })
     }
 
     pub fn clear(&self) {
@@ -59,3 +190,25 @@ impl OpenJTalk {
         }
     }
 }
+
+fn to_cstrings(features: &[NjdFeature]) -> Result<Vec<CString>, String> {
+    features
+        .iter()
+        .map(|feature| {
+            feature.check_fields()?;
+            Ok(CString::new(feature.to_string()).unwrap())
+        })
+        .collect()
+}
+
+unsafe fn take_strings(ptr: *mut *mut c_char, size: usize) -> Vec<String> {
+    let mut result = Vec::new();
+    if size == 0 {
+        return result;
+    }
+    for ptr in std::slice::from_raw_parts(ptr, size) {
+        let c_str = CString::from_raw(*ptr);
+        result.push(c_str.to_str().unwrap().to_string());
+    }
+    result
+}
@@ -0,0 +1,38 @@
+use std::str::FromStr;
+
+const MECAB_UNK_NODE: &str = "1";
+
+/// MeCab による形態素解析の結果の形態素一つ分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MecabMorpheme {
+    pub surface: String,
+    pub feature: Vec<String>,
+    /// 辞書に無く、未知語処理によって作られた形態素かどうか
+    pub is_unknown: bool,
+}
+
+impl MecabMorpheme {
+    /// 辞書の素性に含まれる発音。未知語など、発音を持たない場合は `None`
+    pub fn pron(&self) -> Option<&str> {
+        self.feature
+            .get(8)
+            .map(|pron| pron.as_str())
+            .filter(|pron| *pron != "*")
+    }
+}
+
+impl FromStr for MecabMorpheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.splitn(3, ',');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(stat), Some(surface), Some(feature)) => Ok(MecabMorpheme {
+                surface: surface.to_string(),
+                feature: feature.split(',').map(|field| field.to_string()).collect(),
+                is_unknown: stat == MECAB_UNK_NODE,
+            }),
+            _ => Err(format!("invalid mecab morpheme: {}", s)),
+        }
+    }
+}
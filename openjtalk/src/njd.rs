@@ -0,0 +1,159 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// OpenJTalk の NJD ノード一つ分の素性
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NjdFeature {
+    pub string: String,
+    pub pos: String,
+    pub pos_group1: String,
+    pub pos_group2: String,
+    pub pos_group3: String,
+    pub ctype: String,
+    pub cform: String,
+    pub orig: String,
+    pub read: String,
+    pub pron: String,
+    pub acc: i32,
+    pub mora_size: i32,
+    pub chain_rule: String,
+    pub chain_flag: i32,
+}
+
+impl FromStr for NjdFeature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split(',').collect::<Vec<_>>();
+        if fields.len() != 14 {
+            return Err(format!("invalid njd feature: {}", s));
+        }
+        let parse_int = |field: &str| {
+            field
+                .parse::<i32>()
+                .map_err(|_| format!("invalid njd feature: {}", s))
+        };
+        Ok(NjdFeature {
+            string: fields[0].to_string(),
+            pos: fields[1].to_string(),
+            pos_group1: fields[2].to_string(),
+            pos_group2: fields[3].to_string(),
+            pos_group3: fields[4].to_string(),
+            ctype: fields[5].to_string(),
+            cform: fields[6].to_string(),
+            orig: fields[7].to_string(),
+            read: fields[8].to_string(),
+            pron: fields[9].to_string(),
+            acc: parse_int(fields[10])?,
+            mora_size: parse_int(fields[11])?,
+            chain_rule: fields[12].to_string(),
+            chain_flag: parse_int(fields[13])?,
+        })
+    }
+}
+
+impl NjdFeature {
+    /// `,` 区切りの文字列にしたときに元の素性に戻せるかどうかを確かめる
+    pub fn check_fields(&self) -> Result<(), String> {
+        let fields = [
+            &self.string,
+            &self.pos,
+            &self.pos_group1,
+            &self.pos_group2,
+            &self.pos_group3,
+            &self.ctype,
+            &self.cform,
+            &self.orig,
+            &self.read,
+            &self.pron,
+            &self.chain_rule,
+        ];
+        match fields
+            .iter()
+            .find(|field| field.contains(',') || field.contains('\0'))
+        {
+            Some(field) => Err(format!(
+                "njd feature field must not contain `,` or NUL: `{}`",
+                field
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for NjdFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.string,
+            self.pos,
+            self.pos_group1,
+            self.pos_group2,
+            self.pos_group3,
+            self.ctype,
+            self.cform,
+            self.orig,
+            self.read,
+            self.pron,
+            self.acc,
+            self.mora_size,
+            self.chain_rule,
+            self.chain_flag
+        )
+    }
+}
+
+/// MeCab の N-best 解析で得られた読みの候補
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NBestCandidate {
+    pub features: Vec<NjdFeature>,
+}
+
+impl NBestCandidate {
+    pub fn new(features: Vec<NjdFeature>) -> NBestCandidate {
+        NBestCandidate { features }
+    }
+
+    /// 候補全体の発音をカタカナで返す
+    pub fn pronunciation(&self) -> String {
+        self.features
+            .iter()
+            .map(|feature| feature.pron.as_str())
+            .collect()
+    }
+
+    /// 表層形・発音・アクセント型が同じ候補かどうか
+    pub fn is_same_reading(&self, other: &NBestCandidate) -> bool {
+        self.features.len() == other.features.len()
+            && self
+                .features
+                .iter()
+                .zip(other.features.iter())
+                .all(|(lhs, rhs)| {
+                    lhs.string == rhs.string && lhs.pron == rhs.pron && lhs.acc == rhs.acc
+                })
+    }
+}
+
+#[cfg(test)]
+mod njd_tests {
+    use super::NjdFeature;
+
+    #[test]
+    fn test_njd_feature_round_trip() {
+        let feature_str = "今日,名詞,副詞可能,*,*,*,*,今日,キョウ,キョー,1,2,C3,-1";
+        let feature = feature_str.parse::<NjdFeature>().unwrap();
+        assert_eq!(feature.pron, "キョー");
+        assert_eq!(feature.acc, 1);
+        assert_eq!(feature.mora_size, 2);
+        assert_eq!(feature.to_string(), feature_str);
+        assert!(feature.check_fields().is_ok());
+
+        let feature = NjdFeature {
+            string: "1,000".to_string(),
+            ..feature
+        };
+        assert!(feature.check_fields().is_err());
+    }
+}
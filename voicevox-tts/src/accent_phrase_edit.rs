@@ -0,0 +1,162 @@
+//! アクセント句の編集
+//!
+//! 編集によって構造が変わったアクセント句の番号を返すので、
+//! [`SynthesisEngine::replace_mora_data_of`] でそれらだけ音素長と音高を予測し直せる。
+//!
+//! [`SynthesisEngine::replace_mora_data_of`]: crate::synthesis_engine::SynthesisEngine::replace_mora_data_of
+
+use crate::{frontend::make_pause_mora, model::AccentPhraseModel, mora_list::kana2moras};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccentPhraseEdit {
+    /// アクセント核を `accent` 番目のモーラに移す
+    MoveAccent { phrase: usize, accent: u32 },
+    /// `mora` 番目のモーラの前でアクセント句を分ける
+    Split { phrase: usize, mora: usize },
+    /// `phrase` 番目とその次のアクセント句をつなげる
+    Merge { phrase: usize },
+    /// `phrase` 番目のアクセント句の後ろにポーズを入れる
+    InsertPause { phrase: usize },
+    /// `phrase` 番目のアクセント句の後ろのポーズを取り除く
+    DeletePause { phrase: usize },
+    /// `mora` 番目のモーラの読みをカタカナまたはひらがなの `kana` に置き換える
+    ChangeReading {
+        phrase: usize,
+        mora: usize,
+        kana: String,
+    },
+}
+
+fn check_phrase(accent_phrases: &[AccentPhraseModel], phrase: usize) -> Result<(), String> {
+    if phrase < accent_phrases.len() {
+        Ok(())
+    } else {
+        Err(format!("accent phrase {} is out of range", phrase))
+    }
+}
+
+impl AccentPhraseEdit {
+    /// 編集を適用し、音素長と音高を予測し直すべきアクセント句の番号を返す
+    pub fn apply(&self, accent_phrases: &mut Vec<AccentPhraseModel>) -> Result<Vec<usize>, String> {
+        match *self {
+            AccentPhraseEdit::MoveAccent { phrase, accent } => {
+                check_phrase(accent_phrases, phrase)?;
+                let accent_phrase = &mut accent_phrases[phrase];
+                if accent == 0 || accent as usize > accent_phrase.moras.len() {
+                    return Err(format!(
+                        "accent must be in 1..={}, but got {}",
+                        accent_phrase.moras.len(),
+                        accent
+                    ));
+                }
+                accent_phrase.accent = accent;
+                Ok(vec![phrase])
+            }
+            AccentPhraseEdit::Split { phrase, mora } => {
+                check_phrase(accent_phrases, phrase)?;
+                let (former, latter) = accent_phrases[phrase].split_at(mora)?;
+                accent_phrases[phrase] = former;
+                accent_phrases.insert(phrase + 1, latter);
+                Ok(vec![phrase, phrase + 1])
+            }
+            AccentPhraseEdit::Merge { phrase } => {
+                check_phrase(accent_phrases, phrase + 1)?;
+                let latter = accent_phrases.remove(phrase + 1);
+                accent_phrases[phrase] = accent_phrases[phrase].merge(&latter);
+                Ok(vec![phrase])
+            }
+            AccentPhraseEdit::InsertPause { phrase } => {
+                check_phrase(accent_phrases, phrase)?;
+                if accent_phrases[phrase].pause_mora.is_some() {
+                    return Ok(Vec::new());
+                }
+                accent_phrases[phrase].pause_mora = Some(make_pause_mora());
+                Ok(vec![phrase])
+            }
+            AccentPhraseEdit::DeletePause { phrase } => {
+                check_phrase(accent_phrases, phrase)?;
+                if accent_phrases[phrase].pause_mora.take().is_none() {
+                    return Ok(Vec::new());
+                }
+                Ok(vec![phrase])
+            }
+            AccentPhraseEdit::ChangeReading {
+                phrase,
+                mora,
+                ref kana,
+            } => {
+                check_phrase(accent_phrases, phrase)?;
+                let accent_phrase = &mut accent_phrases[phrase];
+                if mora >= accent_phrase.moras.len() {
+                    return Err(format!("mora {} is out of range", mora));
+                }
+                let moras = kana2moras(kana)?;
+                if moras.is_empty() {
+                    return Err("reading must not be empty".to_string());
+                }
+                accent_phrase.moras.splice(mora..mora + 1, moras);
+                accent_phrase.accent = accent_phrase.accent.min(accent_phrase.moras.len() as u32);
+                Ok(vec![phrase])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod accent_phrase_edit_tests {
+    use super::AccentPhraseEdit;
+    use crate::direct_input::accent_phrases_from_kana;
+
+    #[test]
+    fn test_apply() {
+        let mut accent_phrases = accent_phrases_from_kana("コンニチワ、キョ'ーワ/イ'イ").unwrap();
+
+        let affected = AccentPhraseEdit::Split { phrase: 1, mora: 2 }
+            .apply(&mut accent_phrases)
+            .unwrap();
+        assert_eq!(affected, [1, 2]);
+        assert_eq!(accent_phrases.len(), 4);
+        assert_eq!(accent_phrases[1].accent, 1);
+        assert_eq!(accent_phrases[2].moras[0].text, "ワ");
+
+        AccentPhraseEdit::Merge { phrase: 1 }
+            .apply(&mut accent_phrases)
+            .unwrap();
+        assert_eq!(accent_phrases.len(), 3);
+        assert_eq!(accent_phrases[1].moras.len(), 3);
+        assert_eq!(accent_phrases[1].accent, 1);
+
+        AccentPhraseEdit::MoveAccent {
+            phrase: 0,
+            accent: 3,
+        }
+        .apply(&mut accent_phrases)
+        .unwrap();
+        assert_eq!(accent_phrases[0].accent, 3);
+
+        AccentPhraseEdit::DeletePause { phrase: 0 }
+            .apply(&mut accent_phrases)
+            .unwrap();
+        assert!(accent_phrases[0].pause_mora.is_none());
+
+        AccentPhraseEdit::ChangeReading {
+            phrase: 2,
+            mora: 1,
+            kana: "ーヨ".to_string(),
+        }
+        .apply(&mut accent_phrases)
+        .unwrap_err();
+        AccentPhraseEdit::ChangeReading {
+            phrase: 2,
+            mora: 1,
+            kana: "ヨ".to_string(),
+        }
+        .apply(&mut accent_phrases)
+        .unwrap();
+        assert_eq!(accent_phrases[2].moras[1].text, "ヨ");
+
+        assert!(AccentPhraseEdit::Merge { phrase: 2 }
+            .apply(&mut accent_phrases)
+            .is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! 青空文庫の書式のテキスト
+//!
+//! ルビは読みとして本文に差し込み、`［＃…］` の注記は取り除く。
+//! 見出しの注記がある行は前後に空行を入れて節の区切りにし、章の目印として記録する。
+//! 改ページの注記も節の区切りにする。冒頭の記号の説明と末尾の底本の情報は読まない。
+
+use crate::document::DocumentResult;
+
+/// 見出しを含む、青空文庫のテキストから取り出した本文
+#[derive(Debug, Clone, PartialEq)]
+pub struct AozoraText {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// ルビを読みに置き換え、注記を取り除いた本文。見出しの前後には空行が入る
+    pub body: String,
+    /// 本文に現れる順の見出し
+    pub headings: Vec<String>,
+}
+
+/// 章の始まる時刻（秒）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterMarker {
+    pub title: String,
+    pub start: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AozoraResult {
+    pub document: DocumentResult,
+    pub chapters: Vec<ChapterMarker>,
+}
+
+fn is_separator(line: &str) -> bool {
+    line.chars().count() >= 10 && line.chars().all(|c| c == '-')
+}
+
+fn is_kanji(c: char) -> bool {
+    matches!(c, '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' | '\u{f900}'..='\u{faff}')
+        || matches!(c, '々' | '〆' | 'ヵ' | 'ヶ' | '仝' | '〇')
+}
+
+// 「｜」の無いルビがかかる範囲を決めるための文字の種類
+fn char_class(c: char) -> u8 {
+    if is_kanji(c) {
+        0
+    } else if ('ぁ'..='ゖ').contains(&c) {
+        1
+    } else if ('ァ'..='ヺ').contains(&c) || c == 'ー' {
+        2
+    } else if c.is_ascii_alphanumeric() || ('Ａ'..='ｚ').contains(&c) || ('０'..='９').contains(&c)
+    {
+        3
+    } else {
+        4
+    }
+}
+
+/// ルビの親文字を読みに置き換える
+fn replace_ruby(line: &str) -> String {
+    let mut output: Vec<char> = Vec::new();
+    // 「｜」で始めた親文字の、output 中の位置
+    let mut ruby_start = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '｜' | '|' => ruby_start = Some(output.len()),
+            '《' => {
+                let reading = chars
+                    .by_ref()
+                    .take_while(|c| *c != '》')
+                    .collect::<String>();
+                let start = match ruby_start.take() {
+                    Some(start) => start,
+                    None => {
+                        let class = output.last().map(|c| char_class(*c));
+                        let mut start = output.len();
+                        while start > 0 && Some(char_class(output[start - 1])) == class {
+                            start -= 1;
+                        }
+                        start
+                    }
+                };
+                output.truncate(start);
+                output.extend(reading.chars());
+            }
+            _ => output.push(c),
+        }
+    }
+    output.into_iter().collect()
+}
+
+/// 注記を取り除き、取り除いた注記の中身を返す
+///
+/// 外字の注記はその前の `※` と一緒に取り除く。
+fn remove_annotations(line: &str) -> (String, Vec<String>) {
+    let mut output = String::new();
+    let mut annotations = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("［＃") {
+        let (before, after) = rest.split_at(start);
+        let after = &after["［＃".len()..];
+        let end = after.find('］').unwrap_or(after.len());
+        let before = before.strip_suffix('※').unwrap_or(before);
+        output.push_str(before);
+        annotations.push(after[..end].to_string());
+        rest = after.get(end + '］'.len_utf8()..).unwrap_or_default();
+    }
+    output.push_str(rest);
+    (output, annotations)
+}
+
+/// 青空文庫の書式のテキストを読む
+pub fn parse_aozora(text: &str) -> AozoraText {
+    let lines = text.lines().map(|line| line.trim_end()).collect::<Vec<_>>();
+
+    // 冒頭の題名と著者名、記号の説明を飛ばす
+    let separators = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| is_separator(line))
+        .map(|(i, _)| i)
+        .take(2)
+        .collect::<Vec<_>>();
+    let (title, author, body_start) = if separators.len() == 2 {
+        let header = lines[..separators[0]]
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        (
+            header.first().map(|line| line.trim().to_string()),
+            header
+                .last()
+                .filter(|_| header.len() >= 2)
+                .map(|line| line.trim().to_string()),
+            separators[1] + 1,
+        )
+    } else {
+        (None, None, 0)
+    };
+    // 末尾の底本の情報を飛ばす
+    let body_end = lines[body_start..]
+        .iter()
+        .position(|line| line.starts_with("底本："))
+        .map_or(lines.len(), |i| body_start + i);
+
+    let mut body = Vec::new();
+    let mut headings = Vec::new();
+    let mut in_heading_block = false;
+    for line in &lines[body_start..body_end] {
+        let (line, annotations) = remove_annotations(&replace_ruby(line));
+        let line = line.trim().to_string();
+        let mut is_heading = in_heading_block;
+        for annotation in annotations.iter() {
+            if annotation.contains("見出し") {
+                if annotation.starts_with("ここから") {
+                    in_heading_block = true;
+                } else if annotation.starts_with("ここで") {
+                    in_heading_block = false;
+                }
+                is_heading = true;
+            } else if ["改ページ", "改丁", "改見開き", "改段"].contains(&annotation.as_str())
+            {
+                body.push(String::new());
+                body.push(String::new());
+            }
+        }
+        if is_heading && !line.is_empty() {
+            body.push(String::new());
+            body.push(String::new());
+            body.push(line.clone());
+            body.push(String::new());
+            body.push(String::new());
+            headings.push(line);
+        } else if !line.is_empty() || annotations.is_empty() {
+            body.push(line);
+        }
+    }
+
+    AozoraText {
+        title,
+        author,
+        body: body.join("\n").trim().to_string(),
+        headings,
+    }
+}
+
+/// 合成した文章の中で見出しの文が始まる時刻を求める
+pub(crate) fn find_chapters(headings: &[String], document: &DocumentResult) -> Vec<ChapterMarker> {
+    let mut chapters = Vec::new();
+    let mut sentences = document.sentences().iter();
+    for heading in headings {
+        if let Some(sentence) = sentences.find(|sentence| sentence.text == *heading) {
+            chapters.push(ChapterMarker {
+                title: heading.clone(),
+                start: sentence.start,
+            });
+        }
+    }
+    chapters
+}
+
+#[cfg(test)]
+mod aozora_tests {
+    use super::parse_aozora;
+    use crate::document::{split_sentences, Boundary};
+
+    #[test]
+    fn test_parse_aozora() {
+        let text = "羅生門\n芥川龍之介\n\n\
+            -------------------------------------------------------\n\
+            【テキスト中に現れる記号について】\n\n《》：ルビ\n\
+            -------------------------------------------------------\n\
+            ［＃３字下げ］一［＃「一」は中見出し］\n\
+            \u{3000}ある日の暮方《くれがた》の事である。一人の｜下人《げにん》が、\n\
+            羅生門《らしょうもん》の下で雨やみを待っていた。※［＃「耒＋禺」、第3水準1-90-38］\n\
+            ［＃改ページ］\n\
+            ［＃ここから大見出し］\n二\n［＃ここで大見出し終わり］\n\
+            下人の行方《ゆくえ》は、誰も知らない。\n\n\n\
+            底本：「羅生門・鼻」新潮文庫、新潮社\n入力：平山誠\n";
+        let aozora = parse_aozora(text);
+        assert_eq!(aozora.title.as_deref(), Some("羅生門"));
+        assert_eq!(aozora.author.as_deref(), Some("芥川龍之介"));
+        assert_eq!(aozora.headings, ["一", "二"]);
+        assert!(aozora
+            .body
+            .starts_with("一\n\n\nある日のくれがたの事である。一人のげにんが、"));
+        assert!(aozora
+            .body
+            .contains("らしょうもんの下で雨やみを待っていた。\n"));
+        assert!(aozora.body.ends_with("下人のゆくえは、誰も知らない。"));
+        assert!(!aozora.body.contains('※'));
+
+        let sentences = split_sentences(&aozora.body);
+        assert_eq!(sentences[0].text, "一");
+        assert_eq!(sentences[0].boundary, Boundary::Section);
+        let second = sentences.iter().position(|s| s.text == "二").unwrap();
+        assert_eq!(sentences[second - 1].boundary, Boundary::Section);
+    }
+}
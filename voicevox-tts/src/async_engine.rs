@@ -0,0 +1,309 @@
+//! tokio から使う非同期の音声合成
+//!
+//! OpenJTalk と音声合成モデルの呼び出しはブロックするので、専用のスレッドで実行する。
+//! [`VVTTSEngine`] はスレッド間で受け渡せないため、各スレッドで `factory` から作る。
+//! 中断の要求と期限は処理の段階の間と、区間ごとの合成の間で確認する。
+//! 結果を待つ側は処理の途中でも中断と期限で待つのをやめるので、期限を使う場合は
+//! tokio のランタイムで時間の機能を有効にしておく。
+
+use std::{
+    future,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{mpsc as tokio_mpsc, oneshot},
+    time,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{model::AudioQueryModel, tts::TtsOptions, tts::TtsResult, VVTTSEngine};
+
+type Job = Box<dyn FnOnce(&VVTTSEngine) + Send>;
+
+/// ストリーミング合成で溜めておく区間の数
+const STREAM_BUFFER: usize = 4;
+
+/// 中断の要求と期限
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    pub token: CancellationToken,
+    pub deadline: Option<Instant>,
+}
+
+impl Cancellation {
+    pub fn new() -> Cancellation {
+        Cancellation::default()
+    }
+
+    pub fn with_token(mut self, token: CancellationToken) -> Cancellation {
+        self.token = token;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Cancellation {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Cancellation {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// 中断が要求されているか期限を過ぎていればエラーを返す
+    pub fn check(&self) -> Result<(), String> {
+        if self.token.is_cancelled() {
+            return Err("synthesis was cancelled".to_string());
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err("synthesis deadline exceeded".to_string());
+        }
+        Ok(())
+    }
+
+    /// 中断が要求されるか期限を過ぎるまで待ち、そのエラーを返す
+    pub async fn cancelled(&self) -> String {
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => time::sleep_until(time::Instant::from_std(deadline)).await,
+                None => future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = self.token.cancelled() => "synthesis was cancelled".to_string(),
+            _ = deadline => "synthesis deadline exceeded".to_string(),
+        }
+    }
+}
+
+/// [`VVTTSEngine`] を専用のスレッドで動かす非同期の窓口
+#[derive(Clone)]
+pub struct AsyncVVTTSEngine {
+    sender: mpsc::Sender<Job>,
+}
+
+impl AsyncVVTTSEngine {
+    /// `workers` 個のスレッドを起動し、それぞれで `factory` からエンジンを作る
+    ///
+    /// どれかのエンジンの作成に失敗した場合はそのエラーを返す。
+    pub fn new<F>(workers: usize, factory: F) -> Result<AsyncVVTTSEngine, String>
+    where
+        F: Fn() -> Result<VVTTSEngine, String> + Send + Sync + 'static,
+    {
+        if workers == 0 {
+            return Err("at least one worker is required".to_string());
+        }
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let factory = Arc::new(factory);
+        let (ready_sender, ready_receiver) = mpsc::channel();
+        for i in 0..workers {
+            let receiver = receiver.clone();
+            let factory = factory.clone();
+            let ready_sender = ready_sender.clone();
+            thread::Builder::new()
+                .name(format!("voicevox-tts-{}", i))
+                .spawn(move || {
+                    let engine = match factory() {
+                        Ok(engine) => engine,
+                        Err(e) => {
+                            let _ = ready_sender.send(Err(e));
+                            return;
+                        }
+                    };
+                    let _ = ready_sender.send(Ok(()));
+                    loop {
+                        // ロックはジョブを受け取るまでの間だけ持つ
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            // panic したジョブの結果は送られず、待つ側にエラーが返る。
+                            // スレッドは残して次のジョブを受け取る
+                            Ok(job) => {
+                                let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&engine)));
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                })
+                .map_err(|e| format!("failed to spawn worker thread: {}", e))?;
+        }
+        drop(ready_sender);
+        for _ in 0..workers {
+            ready_receiver
+                .recv()
+                .map_err(|_| "worker thread exited during initialization".to_string())??;
+        }
+        Ok(AsyncVVTTSEngine { sender })
+    }
+
+    /// エンジンを使う処理を専用のスレッドで実行する
+    ///
+    /// 実行を待つ間に中断された場合や、結果を待つ Future が捨てられた場合は実行しない。
+    pub async fn run<T, F>(&self, cancellation: &Cancellation, f: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&VVTTSEngine, &Cancellation) -> Result<T, String> + Send + 'static,
+    {
+        cancellation.check()?;
+        let (result_sender, result_receiver) = oneshot::channel();
+        let job_cancellation = cancellation.clone();
+        let job: Job = Box::new(move |engine| {
+            if result_sender.is_closed() {
+                return;
+            }
+            let result = job_cancellation
+                .check()
+                .and_then(|_| f(engine, &job_cancellation));
+            let _ = result_sender.send(result);
+        });
+        self.sender
+            .send(job)
+            .map_err(|_| "worker threads have stopped".to_string())?;
+
+        tokio::select! {
+            result = result_receiver => {
+                result.map_err(|_| "synthesis job panicked".to_string())?
+            }
+            error = cancellation.cancelled() => Err(error),
+        }
+    }
+
+    pub async fn audio_query(
+        &self,
+        text: String,
+        speaker_id: i64,
+        cancellation: &Cancellation,
+    ) -> Result<AudioQueryModel, String> {
+        self.run(cancellation, move |engine, _| {
+            engine.audio_query(text, speaker_id)
+        })
+        .await
+    }
+
+    pub async fn synthesis(
+        &self,
+        audio_query: AudioQueryModel,
+        speaker_id: i64,
+        enable_interrogative_upspeak: bool,
+        cancellation: &Cancellation,
+    ) -> Result<TtsResult, String> {
+        self.run(cancellation, move |engine, _| {
+            engine.synthesis(audio_query, speaker_id, enable_interrogative_upspeak)
+        })
+        .await
+    }
+
+    /// テキストの解析と音声合成の間でも中断を確認する
+    pub async fn tts(
+        &self,
+        text: String,
+        speaker_id: i64,
+        options: TtsOptions,
+        cancellation: &Cancellation,
+    ) -> Result<TtsResult, String> {
+        self.run(cancellation, move |engine, cancellation| {
+            engine.tts_with_options_checked(text, speaker_id, &options, || cancellation.check())
+        })
+        .await
+    }
+
+    /// 息継ぎごとの音声を順に受け取る
+    ///
+    /// 区間ごとに中断を確認し、中断された場合はそのエラーを最後に送る。
+    /// 受け取る側を捨てると残りの区間は合成しない。
+    pub async fn tts_stream(
+        &self,
+        text: String,
+        speaker_id: i64,
+        options: TtsOptions,
+        cancellation: &Cancellation,
+    ) -> Result<tokio_mpsc::Receiver<Result<Vec<f32>, String>>, String> {
+        let (chunk_sender, chunk_receiver) = tokio_mpsc::channel(STREAM_BUFFER);
+        let (started_sender, started_receiver) = oneshot::channel();
+        let cancellation = cancellation.clone();
+        let job_cancellation = cancellation.clone();
+        let job: Job = Box::new(move |engine| {
+            let stream = job_cancellation
+                .check()
+                .and_then(|_| engine.tts_stream(text, speaker_id, &options));
+            let mut stream = match stream {
+                Ok(stream) => {
+                    let _ = started_sender.send(Ok(()));
+                    stream
+                }
+                Err(e) => {
+                    let _ = started_sender.send(Err(e));
+                    return;
+                }
+            };
+            loop {
+                // 次の区間を合成する前に中断を確認する
+                let chunk = match job_cancellation.check() {
+                    Ok(()) => match stream.next() {
+                        Some(chunk) => chunk,
+                        None => break,
+                    },
+                    Err(e) => Err(e),
+                };
+                let is_err = chunk.is_err();
+                if chunk_sender.blocking_send(chunk).is_err() || is_err {
+                    break;
+                }
+            }
+        });
+        self.sender
+            .send(job)
+            .map_err(|_| "worker threads have stopped".to_string())?;
+
+        tokio::select! {
+            started = started_receiver => {
+                started.map_err(|_| "synthesis job panicked".to_string())??;
+                Ok(chunk_receiver)
+            }
+            error = cancellation.cancelled() => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod async_engine_tests {
+    use std::time::Duration;
+
+    use super::{AsyncVVTTSEngine, Cancellation};
+
+    #[test]
+    fn test_cancellation() {
+        let cancellation = Cancellation::new();
+        assert!(cancellation.check().is_ok());
+        cancellation.token.cancel();
+        assert!(cancellation.check().is_err());
+
+        let cancellation = Cancellation::new().with_timeout(Duration::ZERO);
+        assert!(cancellation.check().is_err());
+        let cancellation = Cancellation::new().with_timeout(Duration::from_secs(60));
+        assert!(cancellation.check().is_ok());
+    }
+
+    #[test]
+    fn test_cancelled_by_deadline() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let cancellation = Cancellation::new().with_timeout(Duration::from_millis(10));
+        let error = runtime.block_on(cancellation.cancelled());
+        assert_eq!(error, "synthesis deadline exceeded");
+    }
+
+    #[test]
+    fn test_factory_error() {
+        let result = AsyncVVTTSEngine::new(2, || Err("no model".to_string()));
+        assert_eq!(result.err(), Some("no model".to_string()));
+    }
+}
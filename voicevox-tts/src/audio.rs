@@ -0,0 +1,174 @@
+//! 合成した音声と、その中の区間
+//!
+//! 文章・台本・SSML の結果は区間ごとに合成した音声をつなげたもので、
+//! 後処理で先頭を取り除いた場合は各区間と音素などの時刻もずらす。
+
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use crate::{
+    encoder::{
+        encode,
+        wav::{write_wav, SampleFormat, WavSpec},
+        OutputFormat,
+    },
+    loudness::{normalize_loudness, LoudnessOptions},
+    postprocess::{post_process, shift_time, PostProcess},
+    tts::Timings,
+};
+
+/// 音声の中での始まりと終わりの時刻（秒）を持つ区間
+pub trait TimedSpan {
+    fn start(&self) -> f32;
+    fn end(&self) -> f32;
+    fn set_time(&mut self, start: f32, end: f32);
+}
+
+impl TimedSpan for Range<f32> {
+    fn start(&self) -> f32 {
+        self.start
+    }
+
+    fn end(&self) -> f32 {
+        self.end
+    }
+
+    fn set_time(&mut self, start: f32, end: f32) {
+        *self = start..end;
+    }
+}
+
+/// 音量を反映した音声と、その中の区間
+#[derive(Debug, Clone)]
+pub struct AudioBuffer<S = Range<f32>> {
+    /// ステレオの場合は左右のサンプルが交互に並ぶ
+    pub samples: Vec<f32>,
+    pub sampling_rate: u32,
+    pub channels: u16,
+    /// 区間ごとに合成してつなげた場合の各区間。[`PostProcess::Declick`] はその境界をつなぎ目として扱う
+    pub spans: Vec<S>,
+    /// 音素・モーラ・アクセント句・単語の、つなげた音声の中での時刻
+    pub timings: Timings,
+}
+
+impl<S: TimedSpan> AudioBuffer<S> {
+    pub fn new(samples: Vec<f32>, sampling_rate: u32, channels: u16) -> AudioBuffer<S> {
+        AudioBuffer {
+            samples,
+            sampling_rate,
+            channels,
+            spans: Vec::new(),
+            timings: Timings {
+                sampling_rate,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// 音声の長さ（秒）
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.channels as f32 / self.sampling_rate as f32
+    }
+
+    /// 音声とその時刻を足し、始まりと終わりの時刻から区間を作る
+    pub(crate) fn push<I, F>(&mut self, samples: I, timings: Timings, span: F)
+    where
+        I: IntoIterator<Item = f32>,
+        F: FnOnce(f32, f32) -> S,
+    {
+        let start = self.duration();
+        self.timings
+            .append(timings, self.samples.len() / self.channels as usize);
+        self.samples.extend(samples);
+        let end = self.duration();
+        self.spans.push(span(start, end));
+    }
+
+    /// `length` 秒の無音を足す
+    pub(crate) fn push_silence(&mut self, length: f32) {
+        let frames = (length * self.sampling_rate as f32).round() as usize;
+        self.samples
+            .resize(self.samples.len() + frames * self.channels as usize, 0.0);
+    }
+
+    pub fn write_wav<W: Write>(&self, writer: W, sample_format: SampleFormat) -> io::Result<W> {
+        let spec = WavSpec {
+            sampling_rate: self.sampling_rate,
+            channels: self.channels,
+            sample_format,
+        };
+        write_wav(writer, spec, &self.samples)
+    }
+
+    pub fn to_wav(&self, sample_format: SampleFormat) -> io::Result<Vec<u8>> {
+        self.write_wav(Vec::new(), sample_format)
+    }
+
+    /// 指定した形式に符号化する
+    pub fn encode(&self, format: OutputFormat) -> Result<Vec<u8>, String> {
+        encode(&self.samples, self.sampling_rate, self.channels, format)
+    }
+
+    /// ラウドネスを正規化し、正規化する前のラウドネス（LUFS）を返す
+    pub fn normalize_loudness(&mut self, options: &LoudnessOptions) -> Option<f32> {
+        normalize_loudness(
+            &mut self.samples,
+            self.sampling_rate,
+            self.channels,
+            options,
+        )
+    }
+
+    /// 後処理をかけ、先頭を取り除いた分だけ各区間と音素などの時刻をずらす
+    pub fn post_process(&mut self, stages: &[PostProcess]) {
+        let joins = self
+            .spans
+            .iter()
+            .flat_map(|span| [span.start(), span.end()])
+            .map(|time| (time * self.sampling_rate as f32).round() as usize)
+            .collect::<Vec<_>>();
+        let removed = post_process(
+            &mut self.samples,
+            self.sampling_rate,
+            self.channels,
+            stages,
+            &joins,
+        );
+        let removed_time = removed as f32 / self.sampling_rate as f32;
+        let duration = self.duration();
+        for span in self.spans.iter_mut() {
+            span.set_time(
+                shift_time(span.start(), removed_time, duration),
+                shift_time(span.end(), removed_time, duration),
+            );
+        }
+        let length = self.samples.len() / self.channels as usize;
+        self.timings.shift(removed, length);
+    }
+}
+
+#[cfg(test)]
+mod audio_tests {
+    use super::AudioBuffer;
+    use crate::{postprocess::PostProcess, tts::Timings};
+
+    #[test]
+    fn test_post_process_shifts_spans() {
+        let mut buffer = AudioBuffer::new(Vec::new(), 1000, 2);
+        buffer.push_silence(0.1);
+        buffer.push(vec![0.5; 400], Timings::default(), |start, end| start..end);
+        buffer.push_silence(0.05);
+        buffer.push(vec![0.5; 200], Timings::default(), |start, end| start..end);
+        assert_eq!(buffer.spans, vec![0.1..0.3, 0.35..0.45]);
+
+        buffer.post_process(&[PostProcess::Trim {
+            threshold: -40.0,
+            padding: 0.0,
+        }]);
+        assert!((buffer.duration() - 0.35).abs() < 1e-6);
+        assert!((buffer.spans[1].start - 0.25).abs() < 1e-6);
+        assert!((buffer.spans[1].end - 0.35).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,360 @@
+//! AudioQuery の組み立てと検証
+
+use std::ops::RangeInclusive;
+
+use crate::{
+    acoustic_feature_extractor::OjtPhoneme,
+    model::{AccentPhraseModel, AudioQueryModel, MoraModel},
+    mora_list::{mora2text, text2mora_map},
+    synthesis_engine::{DEFAULT_SAMPLING_RATE, MORA_PHONEME_LIST},
+};
+
+/// 話速。1.0 が標準で、大きいほど速い
+pub const SPEED_SCALE_RANGE: RangeInclusive<f32> = 0.5..=2.0;
+/// 音高。0.0 が標準で、1.0 上げると 1 オクターブ高くなる
+pub const PITCH_SCALE_RANGE: RangeInclusive<f32> = -0.15..=0.15;
+/// 抑揚。1.0 が標準で、0.0 にすると音高が平らになる
+pub const INTONATION_SCALE_RANGE: RangeInclusive<f32> = 0.0..=2.0;
+/// 音量。1.0 が標準
+pub const VOLUME_SCALE_RANGE: RangeInclusive<f32> = 0.0..=2.0;
+/// 音声の前後の無音の長さ（秒）
+pub const PHONEME_LENGTH_RANGE: RangeInclusive<f32> = 0.0..=1.5;
+/// 出力のサンプリングレート。[`DEFAULT_SAMPLING_RATE`] 以外は [`resampler`](crate::resampler) で変換する
+pub const OUTPUT_SAMPLING_RATE_RANGE: RangeInclusive<u32> = 8000..=192000;
+
+/// [`AudioQueryModel`] を組み立てる
+///
+/// 各値の範囲は `*_RANGE` の定数を参照。範囲は [`AudioQueryBuilder::build`] で検証する。
+#[derive(Debug, Clone)]
+pub struct AudioQueryBuilder {
+    query: AudioQueryModel,
+}
+
+impl AudioQueryBuilder {
+    pub fn new(accent_phrases: Vec<AccentPhraseModel>) -> AudioQueryBuilder {
+        AudioQueryBuilder {
+            query: AudioQueryModel {
+                accent_phrases,
+                speed_scale: 1.0,
+                pitch_scale: 0.0,
+                intonation_scale: 1.0,
+                volume_scale: 1.0,
+                pre_phoneme_length: 0.1,
+                post_phoneme_length: 0.1,
+                output_sampling_rate: DEFAULT_SAMPLING_RATE,
+                output_stereo: false,
+                kana: "".to_string(),
+            },
+        }
+    }
+
+    pub fn speed_scale(mut self, speed_scale: f32) -> Self {
+        self.query.speed_scale = speed_scale;
+        self
+    }
+
+    pub fn pitch_scale(mut self, pitch_scale: f32) -> Self {
+        self.query.pitch_scale = pitch_scale;
+        self
+    }
+
+    pub fn intonation_scale(mut self, intonation_scale: f32) -> Self {
+        self.query.intonation_scale = intonation_scale;
+        self
+    }
+
+    pub fn volume_scale(mut self, volume_scale: f32) -> Self {
+        self.query.volume_scale = volume_scale;
+        self
+    }
+
+    pub fn pre_phoneme_length(mut self, pre_phoneme_length: f32) -> Self {
+        self.query.pre_phoneme_length = pre_phoneme_length;
+        self
+    }
+
+    pub fn post_phoneme_length(mut self, post_phoneme_length: f32) -> Self {
+        self.query.post_phoneme_length = post_phoneme_length;
+        self
+    }
+
+    pub fn output_sampling_rate(mut self, output_sampling_rate: u32) -> Self {
+        self.query.output_sampling_rate = output_sampling_rate;
+        self
+    }
+
+    pub fn output_stereo(mut self, output_stereo: bool) -> Self {
+        self.query.output_stereo = output_stereo;
+        self
+    }
+
+    pub fn kana(mut self, kana: String) -> Self {
+        self.query.kana = kana;
+        self
+    }
+
+    pub fn build(self) -> Result<AudioQueryModel, String> {
+        self.query.validate()?;
+        Ok(self.query)
+    }
+
+    /// エンジンが作ったアクセント句からクエリを作る。値の範囲は調べず、構造だけを検証する
+    pub(crate) fn build_generated(self) -> Result<AudioQueryModel, String> {
+        self.query.validate_structure()?;
+        Ok(self.query)
+    }
+}
+
+pub(crate) fn check_range(
+    name: &str,
+    value: f32,
+    range: RangeInclusive<f32>,
+) -> Result<(), String> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} must be in {}..={}, but got {}",
+            name,
+            range.start(),
+            range.end(),
+            value
+        ))
+    }
+}
+
+fn check_length(name: &str, value: f32) -> Result<(), String> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{} must be non-negative, but got {}", name, value))
+    }
+}
+
+fn is_known_phoneme(phoneme: &str) -> bool {
+    loop {
+        if let Ok(map) = OjtPhoneme::phoneme_map().lock() {
+            return map.contains_key(phoneme);
+        }
+    }
+}
+
+// 合成中の panic を防ぐための、モーラの音素と長さの検証
+fn check_mora_structure(mora: &MoraModel, location: &str) -> Result<(), String> {
+    if !is_known_phoneme(&mora.vowel) {
+        return Err(format!("{}: unknown vowel `{}`", location, mora.vowel));
+    }
+    if let Some(ref consonant) = mora.consonant {
+        if !is_known_phoneme(consonant) {
+            return Err(format!("{}: unknown consonant `{}`", location, consonant));
+        }
+        match mora.consonant_length {
+            Some(consonant_length) => {
+                check_length(&format!("{}.consonant_length", location), consonant_length)?
+            }
+            None => return Err(format!("{}: consonant_length is missing", location)),
+        }
+    }
+    check_length(&format!("{}.vowel_length", location), mora.vowel_length)
+}
+
+fn validate_mora(mora: &MoraModel, location: &str) -> Result<(), String> {
+    check_mora_structure(mora, location)?;
+    if !MORA_PHONEME_LIST.contains(&mora.vowel.as_str()) || mora.vowel == "pau" {
+        return Err(format!("{}: unknown vowel `{}`", location, mora.vowel));
+    }
+    if let Some(ref consonant) = mora.consonant {
+        if MORA_PHONEME_LIST.contains(&consonant.as_str()) {
+            return Err(format!("{}: unknown consonant `{}`", location, consonant));
+        }
+    }
+    check_length(&format!("{}.pitch", location), mora.pitch)?;
+
+    // 無声化した母音のモーラも、有声のモーラと同じ文字で表す
+    let consonant = mora.consonant.as_deref().unwrap_or("");
+    let vowel = match mora.vowel.as_str() {
+        "N" | "cl" => mora.vowel.clone(),
+        vowel => vowel.to_lowercase(),
+    };
+    let is_consistent = mora2text(format!("{}{}", consonant, vowel)) == mora.text
+        || text2mora_map().get(mora.text.as_str()) == Some(&(consonant, vowel.as_str()));
+    if !is_consistent {
+        return Err(format!(
+            "{}: text `{}` does not match the phonemes `{}{}`",
+            location, mora.text, consonant, mora.vowel
+        ));
+    }
+    Ok(())
+}
+
+fn validate_pause_mora(mora: &MoraModel, location: &str) -> Result<(), String> {
+    if mora.vowel != "pau" || mora.consonant.is_some() {
+        return Err(format!("{}: pause mora must be `pau`", location));
+    }
+    check_length(&format!("{}.vowel_length", location), mora.vowel_length)
+}
+
+impl AudioQueryModel {
+    pub fn builder(accent_phrases: Vec<AccentPhraseModel>) -> AudioQueryBuilder {
+        AudioQueryBuilder::new(accent_phrases)
+    }
+
+    /// 音声合成に渡しても問題の無いクエリかを調べる
+    ///
+    /// 外部から受け取ったクエリは、合成の前にこれで検証する。
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_structure()?;
+        check_range("speed_scale", self.speed_scale, SPEED_SCALE_RANGE)?;
+        check_range("pitch_scale", self.pitch_scale, PITCH_SCALE_RANGE)?;
+        check_range(
+            "intonation_scale",
+            self.intonation_scale,
+            INTONATION_SCALE_RANGE,
+        )?;
+        check_range("volume_scale", self.volume_scale, VOLUME_SCALE_RANGE)?;
+        check_range(
+            "pre_phoneme_length",
+            self.pre_phoneme_length,
+            PHONEME_LENGTH_RANGE,
+        )?;
+        check_range(
+            "post_phoneme_length",
+            self.post_phoneme_length,
+            PHONEME_LENGTH_RANGE,
+        )?;
+        if !OUTPUT_SAMPLING_RATE_RANGE.contains(&self.output_sampling_rate) {
+            return Err(format!(
+                "output_sampling_rate must be in {}..={}, but got {}",
+                OUTPUT_SAMPLING_RATE_RANGE.start(),
+                OUTPUT_SAMPLING_RATE_RANGE.end(),
+                self.output_sampling_rate
+            ));
+        }
+
+        for (i, accent_phrase) in self.accent_phrases.iter().enumerate() {
+            let location = format!("accent_phrases[{}]", i);
+            for (j, mora) in accent_phrase.moras.iter().enumerate() {
+                validate_mora(mora, &format!("{}.moras[{}]", location, j))?;
+            }
+            if let Some(ref pause_mora) = accent_phrase.pause_mora {
+                validate_pause_mora(pause_mora, &format!("{}.pause_mora", location))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 合成中に panic しないために最低限必要な検証
+    ///
+    /// 音素が既知であること、子音の長さがあること、長さが有限の非負の値であることなどを調べる。
+    /// 値の範囲やモーラの文字の整合性は調べないので、範囲外の値でも合成できる。
+    pub(crate) fn validate_structure(&self) -> Result<(), String> {
+        if !(self.speed_scale.is_finite() && self.speed_scale > 0.0) {
+            return Err(format!(
+                "speed_scale must be positive, but got {}",
+                self.speed_scale
+            ));
+        }
+        check_length("pre_phoneme_length", self.pre_phoneme_length)?;
+        check_length("post_phoneme_length", self.post_phoneme_length)?;
+        if self.output_sampling_rate == 0 {
+            return Err("output_sampling_rate must be positive".to_string());
+        }
+
+        check_accent_phrases(&self.accent_phrases)
+    }
+}
+
+/// 合成中に panic しないための、アクセント句の構造の検証
+///
+/// モーラがあること、アクセント位置がモーラの範囲内にあること、各モーラの音素と長さを調べる。
+pub(crate) fn check_accent_phrases(accent_phrases: &[AccentPhraseModel]) -> Result<(), String> {
+    for (i, accent_phrase) in accent_phrases.iter().enumerate() {
+        let location = format!("accent_phrases[{}]", i);
+        if accent_phrase.moras.is_empty() {
+            return Err(format!("{}: moras are empty", location));
+        }
+        if !(1..=accent_phrase.moras.len()).contains(&(accent_phrase.accent as usize)) {
+            return Err(format!(
+                "{}: accent must be in 1..={}, but got {}",
+                location,
+                accent_phrase.moras.len(),
+                accent_phrase.accent
+            ));
+        }
+        for (j, mora) in accent_phrase.moras.iter().enumerate() {
+            check_mora_structure(mora, &format!("{}.moras[{}]", location, j))?;
+        }
+        if let Some(ref pause_mora) = accent_phrase.pause_mora {
+            check_mora_structure(pause_mora, &format!("{}.pause_mora", location))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod audio_query_tests {
+    use crate::{direct_input::accent_phrases_from_kana, model::AudioQueryModel};
+
+    #[test]
+    fn test_validate() {
+        let accent_phrases = accent_phrases_from_kana("コンニチワ、キョ'ーワ/ヲ'").unwrap();
+        let query = AudioQueryModel::builder(accent_phrases.clone())
+            .speed_scale(1.5)
+            .output_sampling_rate(48000)
+            .build()
+            .unwrap();
+        assert_eq!(query.speed_scale, 1.5);
+
+        assert!(AudioQueryModel::builder(accent_phrases.clone())
+            .speed_scale(0.0)
+            .build()
+            .is_err());
+        assert!(AudioQueryModel::builder(accent_phrases.clone())
+            .output_sampling_rate(4000)
+            .build()
+            .is_err());
+
+        let mut query = AudioQueryModel::builder(accent_phrases).build().unwrap();
+        query.accent_phrases[0].accent = 6;
+        assert!(query
+            .validate()
+            .unwrap_err()
+            .starts_with("accent_phrases[0]"));
+        query.accent_phrases[0].accent = 5;
+        query.accent_phrases[0].moras[0].text = "カ".to_string();
+        assert!(query.validate().is_err());
+        query.accent_phrases[0].moras[0].text = "コ".to_string();
+        query.accent_phrases[0].moras[0].vowel = "x".to_string();
+        assert!(query.validate().is_err());
+        query.accent_phrases[0].moras[0].vowel = "O".to_string();
+        query.validate().unwrap();
+        query.accent_phrases[0].pause_mora.as_mut().unwrap().vowel = "a".to_string();
+        assert!(query.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_structure() {
+        let accent_phrases = accent_phrases_from_kana("コンニチワ、キョ'ーワ/ヲ'").unwrap();
+        let mut query = AudioQueryModel::builder(accent_phrases).build().unwrap();
+        // 範囲外の値は、合成の妨げにならないので通す
+        query.speed_scale = 3.0;
+        query.pitch_scale = 0.5;
+        query.validate_structure().unwrap();
+        assert!(query.validate().is_err());
+        // アクセント位置は音高の予測で使うので範囲外を通さない
+        query.accent_phrases[0].accent = 0;
+        assert!(query.validate_structure().is_err());
+        query.accent_phrases[0].accent = 6;
+        assert!(query.validate_structure().is_err());
+        query.accent_phrases[0].accent = 5;
+
+        query.speed_scale = 0.0;
+        assert!(query.validate_structure().is_err());
+        query.speed_scale = 1.0;
+        query.accent_phrases[0].moras[2].consonant_length = None;
+        assert!(query.validate_structure().is_err());
+        query.accent_phrases[0].moras[2].consonant_length = Some(0.1);
+        query.accent_phrases[1].moras.clear();
+        assert!(query.validate_structure().is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! 複数の話者による台本の音声合成
+//!
+//! 台本は `話者名: セリフ` の行を並べたテキスト、または JSON か TOML で書く。
+//! 話者名の後ろに `四国めたん（あまあま）: ...` のように括弧でスタイル名を付けられる。
+//! テキストの台本では空行と `#` で始まる行を無視する。
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::{AudioBuffer, TimedSpan},
+    audio_query::check_range,
+    tts::TtsResult,
+};
+
+/// パンの範囲。-1.0 が左、1.0 が右
+pub const PAN_RANGE: std::ops::RangeInclusive<f32> = -1.0..=1.0;
+
+/// 台本の 1 行
+///
+/// プリセットを指定した場合はその合成パラメータを使い、さらに各値で上書きする。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DialogueLine {
+    /// 話者名。数字の場合は話者 ID として扱う
+    pub speaker: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_scale: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch_scale: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intonation_scale: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_scale: Option<f32>,
+    /// この行の後ろの無音（秒）。省略した場合は台本の `pause_after` を使う
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_after: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DialogueScript {
+    /// 行の間の無音（秒）
+    #[serde(default = "default_pause_after")]
+    pub pause_after: f32,
+    /// 話者名ごとのパン。一つでも指定した場合はステレオで出力する
+    #[serde(default)]
+    pub pans: BTreeMap<String, f32>,
+    pub lines: Vec<DialogueLine>,
+}
+
+fn default_pause_after() -> f32 {
+    0.4
+}
+
+impl Default for DialogueScript {
+    fn default() -> Self {
+        DialogueScript {
+            pause_after: default_pause_after(),
+            pans: BTreeMap::new(),
+            lines: Vec::new(),
+        }
+    }
+}
+
+/// `四国めたん（あまあま）` を話者名とスタイル名に分ける
+pub(crate) fn split_speaker(speaker: &str) -> (String, Option<String>) {
+    let speaker = speaker.trim();
+    for (open, close) in [('（', '）'), ('(', ')')] {
+        if let Some(stripped) = speaker.strip_suffix(close) {
+            if let Some((name, style)) = stripped.split_once(open) {
+                return (name.trim().to_string(), Some(style.trim().to_string()));
+            }
+        }
+    }
+    (speaker.to_string(), None)
+}
+
+impl DialogueScript {
+    /// `話者名: セリフ` の行を並べたテキストを読む
+    pub fn parse_text(text: &str) -> Result<DialogueScript, String> {
+        let mut lines = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (speaker, text) = line
+                .split_once(['：', ':'])
+                .ok_or_else(|| format!("line {}: expected `speaker: text`", number + 1))?;
+            let (speaker, style) = split_speaker(speaker);
+            if speaker.is_empty() {
+                return Err(format!("line {}: speaker is empty", number + 1));
+            }
+            lines.push(DialogueLine {
+                speaker,
+                style,
+                text: text.trim().to_string(),
+                ..Default::default()
+            });
+        }
+        Ok(DialogueScript {
+            lines,
+            ..Default::default()
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<DialogueScript, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    pub fn from_toml(toml: &str) -> Result<DialogueScript, String> {
+        toml::from_str(toml).map_err(|e| e.to_string())
+    }
+
+    /// 拡張子が `.json` の場合は JSON、`.toml` の場合は TOML、それ以外はテキストとして読む
+    pub fn load(path: &Path) -> Result<DialogueScript, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let script = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => DialogueScript::from_json(&content)?,
+            Some("toml") => DialogueScript::from_toml(&content)?,
+            _ => DialogueScript::parse_text(&content)?,
+        };
+        script.validate()?;
+        Ok(script)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        check_range("pause_after", self.pause_after, 0.0..=f32::MAX)?;
+        for (speaker, pan) in self.pans.iter() {
+            check_range(&format!("pan of `{}`", speaker), *pan, PAN_RANGE)?;
+        }
+        for (i, line) in self.lines.iter().enumerate() {
+            if let Some(pause_after) = line.pause_after {
+                check_range(
+                    &format!("pause_after of line {}", i + 1),
+                    pause_after,
+                    0.0..=f32::MAX,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_stereo(&self) -> bool {
+        !self.pans.is_empty()
+    }
+}
+
+/// 音声の中での行の位置（秒）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineTiming {
+    pub speaker: String,
+    pub style: Option<String>,
+    pub speaker_id: i64,
+    pub text: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TimedSpan for LineTiming {
+    fn start(&self) -> f32 {
+        self.start
+    }
+
+    fn end(&self) -> f32 {
+        self.end
+    }
+
+    fn set_time(&mut self, start: f32, end: f32) {
+        (self.start, self.end) = (start, end);
+    }
+}
+
+/// 台本の音声合成の結果
+#[derive(Debug, Clone)]
+pub struct DialogueResult {
+    /// 区間は行ごとの位置
+    pub audio: AudioBuffer<LineTiming>,
+}
+
+impl DialogueResult {
+    pub(crate) fn new(script: &DialogueScript, sampling_rate: u32) -> DialogueResult {
+        let channels = if script.is_stereo() { 2 } else { 1 };
+        DialogueResult {
+            audio: AudioBuffer::new(Vec::new(), sampling_rate, channels),
+        }
+    }
+
+    /// モノラルで合成した行の音声をパンを反映して足し、その後ろに無音を足す
+    pub(crate) fn push(
+        &mut self,
+        script: &DialogueScript,
+        line: &DialogueLine,
+        speaker_id: i64,
+        result: TtsResult,
+        pause_after: f32,
+    ) {
+        let samples = if self.audio.channels == 2 {
+            let pan = script.pans.get(&line.speaker).copied().unwrap_or(0.0);
+            // 中央で音量が変わらないようにバランスで振る
+            let left = (1.0 - pan).min(1.0);
+            let right = (1.0 + pan).min(1.0);
+            result
+                .audio
+                .samples
+                .iter()
+                .flat_map(|sample| [sample * left, sample * right])
+                .collect()
+        } else {
+            result.audio.samples
+        };
+        self.audio
+            .push(samples, result.audio.timings, |start, end| LineTiming {
+                speaker: line.speaker.clone(),
+                style: line.style.clone(),
+                speaker_id,
+                text: line.text.clone(),
+                start,
+                end,
+            });
+        self.audio.push_silence(pause_after);
+    }
+
+    /// 行ごとの位置
+    pub fn lines(&self) -> &[LineTiming] {
+        &self.audio.spans
+    }
+
+    /// 各行の時刻を JSON で返す
+    pub fn manifest(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self.lines()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod dialogue_tests {
+    use super::DialogueScript;
+
+    #[test]
+    fn test_parse_script() {
+        let script = DialogueScript::parse_text(
+            "# 第一話\n四国めたん（あまあま）: こんにちは。\n\nずんだもん：よろしくなのだ\n",
+        )
+        .unwrap();
+        assert_eq!(script.lines.len(), 2);
+        assert_eq!(script.lines[0].speaker, "四国めたん");
+        assert_eq!(script.lines[0].style.as_deref(), Some("あまあま"));
+        assert_eq!(script.lines[0].text, "こんにちは。");
+        assert_eq!(script.lines[1].speaker, "ずんだもん");
+        assert_eq!(script.lines[1].style, None);
+        assert!(DialogueScript::parse_text("こんにちは").is_err());
+
+        let script = DialogueScript::from_json(
+            r#"{"pans": {"ずんだもん": 0.5},
+                "lines": [{"speaker": "ずんだもん", "text": "なのだ", "preset": "早口", "pauseAfter": 1.0}]}"#,
+        )
+        .unwrap();
+        script.validate().unwrap();
+        assert_eq!(script.pause_after, 0.4);
+        assert_eq!(script.lines[0].preset.as_deref(), Some("早口"));
+        assert_eq!(script.lines[0].pause_after, Some(1.0));
+
+        let script = DialogueScript::from_toml(
+            "pauseAfter = 0.5\n[pans]\n\"四国めたん\" = -2.0\n[[lines]]\nspeaker = \"四国めたん\"\ntext = \"はい\"\n",
+        )
+        .unwrap();
+        assert!(script.validate().is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! 形態素解析を通さずに、読みや音素列から直接アクセント句を作る
+//!
+//! どちらの入力でも、以下の記号でアクセントと区切りを指定できる。
+//!
+//! - `'`: 直前のモーラをアクセント核とする。省略した場合は平板型になる
+//! - `/`: アクセント句の区切り
+//! - `、`: ポーズを伴うアクセント句の区切り
+//! - `？`: アクセント句の末尾に付けると疑問文の抑揚になる
+
+use crate::{
+    acoustic_feature_extractor::OjtPhoneme,
+    frontend::make_pause_mora,
+    model::{AccentPhraseModel, MoraModel},
+    mora_list::{kana2moras, mora2text},
+};
+
+const VOWEL_PHONEMES: &[&str] = &["a", "i", "u", "e", "o", "A", "I", "U", "E", "O", "N", "cl"];
+
+struct PhraseSource {
+    text: String,
+    // 入力テキスト中での開始位置（文字単位）
+    position: usize,
+    has_pause: bool,
+}
+
+fn split_phrases(text: &str, is_phoneme: bool) -> Vec<PhraseSource> {
+    let mut phrases = Vec::new();
+    let mut current = String::new();
+    let mut position = 0;
+    for (i, c) in text.chars().enumerate() {
+        let (is_delimiter, has_pause) = match c {
+            '/' | '／' => (true, false),
+            '、' | '，' => (true, true),
+            ',' if !is_phoneme => (true, true),
+            _ => (false, false),
+        };
+        if is_delimiter {
+            phrases.push(PhraseSource {
+                text: current.clone(),
+                position,
+                has_pause,
+            });
+            current.clear();
+            position = i + 1;
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.trim().is_empty() || phrases.is_empty() {
+        phrases.push(PhraseSource {
+            text: current,
+            position,
+            has_pause: false,
+        });
+    }
+    phrases
+}
+
+fn strip_interrogative(text: &str) -> (&str, bool) {
+    let trimmed = text.trim_end();
+    match trimmed
+        .strip_suffix('？')
+        .or_else(|| trimmed.strip_suffix('?'))
+    {
+        Some(text) => (text, true),
+        None => (text, false),
+    }
+}
+
+fn build_accent_phrases(
+    phrase_sources: Vec<PhraseSource>,
+    parse_phrase: impl Fn(&str, usize) -> Result<(Vec<MoraModel>, Option<usize>), String>,
+) -> Result<Vec<AccentPhraseModel>, String> {
+    let mut accent_phrases = Vec::new();
+    for source in phrase_sources {
+        let (text, is_interrogative) = strip_interrogative(&source.text);
+        let (moras, accent) = parse_phrase(text, source.position)?;
+        if moras.is_empty() {
+            return Err(format!("empty accent phrase at {}", source.position));
+        }
+        let accent = accent.unwrap_or(moras.len()) as u32;
+        accent_phrases.push(AccentPhraseModel {
+            moras,
+            accent,
+            pause_mora: if source.has_pause {
+                Some(make_pause_mora())
+            } else {
+                None
+            },
+            is_interrogative,
+        });
+    }
+    // 末尾の区切りによるポーズは不要
+    if let Some(last) = accent_phrases.last_mut() {
+        last.pause_mora = None;
+    }
+    Ok(accent_phrases)
+}
+
+/// カタカナまたはひらがなの読みからアクセント句を作る
+///
+/// 例: `コンニチワ/キョ'ーワ、イ'イ/テ'ンキデスネ`
+pub fn accent_phrases_from_kana(kana: &str) -> Result<Vec<AccentPhraseModel>, String> {
+    build_accent_phrases(split_phrases(kana, false), |text, position| {
+        let mut kana = String::new();
+        let mut accent = None;
+        for (i, c) in text.chars().enumerate() {
+            if c == '\'' || c == '’' {
+                if accent.is_some() {
+                    return Err(format!("accent is specified twice at {}", position + i));
+                }
+                let mora_size = kana2moras(&kana)
+                    .map_err(|e| format!("{} (phrase at {})", e, position))?
+                    .len();
+                if mora_size == 0 {
+                    return Err(format!("accent must follow a mora at {}", position + i));
+                }
+                accent = Some(mora_size);
+            } else if !c.is_whitespace() {
+                kana.push(c);
+            }
+        }
+        let moras = kana2moras(&kana).map_err(|e| format!("{} (phrase at {})", e, position))?;
+        Ok((moras, accent))
+    })
+}
+
+/// 空白区切りの音素列からアクセント句を作る
+///
+/// 音素には [`OjtPhoneme::phoneme_map`] にあるものが使える。
+/// アクセント核の記号 `'` は母音の直後に付ける。
+///
+/// 例: `k o N n i ch i w a / ky o' o w a`
+pub fn accent_phrases_from_phonemes(phonemes: &str) -> Result<Vec<AccentPhraseModel>, String> {
+    build_accent_phrases(split_phrases(phonemes, true), |text, position| {
+        let mut moras = Vec::new();
+        let mut accent = None;
+        let mut consonant: Option<String> = None;
+        for token in text.split_whitespace() {
+            let (phoneme, is_accent) =
+                match token.strip_suffix('\'').or_else(|| token.strip_suffix('’')) {
+                    Some(phoneme) => (phoneme, true),
+                    None => (token, false),
+                };
+            let is_known = loop {
+                if let Ok(map) = OjtPhoneme::phoneme_map().lock() {
+                    break map.contains_key(phoneme);
+                }
+            };
+            if !is_known || phoneme == "pau" {
+                return Err(format!(
+                    "unknown phoneme `{}` in phrase at {}",
+                    phoneme, position
+                ));
+            }
+
+            if VOWEL_PHONEMES.contains(&phoneme) {
+                let mora_text = match (consonant.as_ref(), phoneme) {
+                    (None, "N") | (None, "cl") => phoneme.to_string(),
+                    (Some(consonant), vowel) => format!("{}{}", consonant, vowel.to_lowercase()),
+                    (None, vowel) => vowel.to_lowercase(),
+                };
+                moras.push(MoraModel {
+                    text: mora2text(mora_text),
+                    consonant_length: consonant.as_ref().map(|_| 0.0),
+                    consonant: consonant.take(),
+                    vowel: phoneme.to_string(),
+                    vowel_length: 0.0,
+                    pitch: 0.0,
+                });
+                if is_accent {
+                    if accent.is_some() {
+                        return Err(format!(
+                            "accent is specified twice in phrase at {}",
+                            position
+                        ));
+                    }
+                    accent = Some(moras.len());
+                }
+            } else {
+                if is_accent {
+                    return Err(format!(
+                        "accent must follow a vowel: `{}` in phrase at {}",
+                        token, position
+                    ));
+                }
+                if let Some(consonant) = consonant {
+                    return Err(format!(
+                        "consonant `{}` is not followed by a vowel in phrase at {}",
+                        consonant, position
+                    ));
+                }
+                consonant = Some(phoneme.to_string());
+            }
+        }
+        if let Some(consonant) = consonant {
+            return Err(format!(
+                "consonant `{}` is not followed by a vowel in phrase at {}",
+                consonant, position
+            ));
+        }
+        Ok((moras, accent))
+    })
+}
+
+#[cfg(test)]
+mod direct_input_tests {
+    use super::{accent_phrases_from_kana, accent_phrases_from_phonemes};
+
+    #[test]
+    fn test_accent_phrases_from_kana() {
+        let accent_phrases =
+            accent_phrases_from_kana("コンニチワ、きょ'うわ/イ'イ/テンキ？").unwrap();
+        assert_eq!(accent_phrases.len(), 4);
+        assert_eq!(accent_phrases[0].accent, 5);
+        assert!(accent_phrases[0].pause_mora.is_some());
+        assert_eq!(accent_phrases[1].accent, 1);
+        assert_eq!(accent_phrases[1].moras[0].text, "キョ");
+        assert!(accent_phrases[1].pause_mora.is_none());
+        assert!(accent_phrases[3].is_interrogative);
+        assert!(accent_phrases_from_kana("コン//ニチワ").is_err());
+        assert!(accent_phrases_from_kana("'コンニチワ").is_err());
+    }
+
+    #[test]
+    fn test_accent_phrases_from_phonemes() {
+        let accent_phrases =
+            accent_phrases_from_phonemes("k o N n i ch i w a / ky o' o w a").unwrap();
+        assert_eq!(accent_phrases.len(), 2);
+        assert_eq!(accent_phrases[0].moras.len(), 5);
+        assert_eq!(accent_phrases[0].moras[1].text, "ン");
+        assert_eq!(accent_phrases[0].moras[3].text, "チ");
+        assert_eq!(accent_phrases[1].accent, 1);
+        assert!(accent_phrases_from_phonemes("k k o").is_err());
+        assert!(accent_phrases_from_phonemes("x a").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! 長い文章の音声合成
+//!
+//! 文章を文に分け、文ごとに合成した音声を境界の種類に応じた無音でつなぐ。
+//! 改行は段落の区切り、空行は節の区切りとして扱う。
+
+use crate::{
+    audio::{AudioBuffer, TimedSpan},
+    tts::{TtsOptions, TtsResult},
+};
+
+const SENTENCE_TERMINATORS: &[char] = &['。', '．', '！', '？', '!', '?'];
+const OPENING_BRACKETS: &[char] = &['「', '『', '（', '(', '【', '〈', '《', '“'];
+const CLOSING_BRACKETS: &[char] = &['」', '』', '）', ')', '】', '〉', '》', '”'];
+
+/// 文の後ろの区切りの種類。後ろのものほど大きい区切りになる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Boundary {
+    Sentence,
+    Paragraph,
+    Section,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sentence {
+    pub text: String,
+    /// この文の後ろの区切り
+    pub boundary: Boundary,
+}
+
+/// 文章を文に分ける
+///
+/// 括弧の中の句点では分けず、`！？` のような連続した記号と後ろの閉じ括弧は前の文に含める。
+/// 読み上げる文字を含まない文は取り除く。
+pub fn split_sentences(text: &str) -> Vec<Sentence> {
+    let mut sentences: Vec<Sentence> = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut chars = text.chars().peekable();
+
+    let mut push = |current: &mut String, boundary: Boundary| {
+        let text = current.trim().to_string();
+        current.clear();
+        if text.chars().any(char::is_alphanumeric) {
+            sentences.push(Sentence { text, boundary });
+        } else if let Some(last) = sentences.last_mut() {
+            // 空の文の区切りは、より大きい方を前の文に引き継ぐ
+            if boundary > last.boundary {
+                last.boundary = boundary;
+            }
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        if c == '\r' {
+            continue;
+        }
+        if c == '\n' {
+            let mut boundary = Boundary::Paragraph;
+            while let Some(&next) = chars.peek() {
+                if next == '\n' {
+                    boundary = Boundary::Section;
+                } else if !next.is_whitespace() {
+                    break;
+                }
+                chars.next();
+            }
+            depth = 0;
+            push(&mut current, boundary);
+            continue;
+        }
+
+        current.push(c);
+        if OPENING_BRACKETS.contains(&c) {
+            depth += 1;
+        } else if CLOSING_BRACKETS.contains(&c) {
+            depth = depth.saturating_sub(1);
+        } else if SENTENCE_TERMINATORS.contains(&c) && depth == 0 {
+            while let Some(&next) = chars.peek() {
+                if SENTENCE_TERMINATORS.contains(&next) || CLOSING_BRACKETS.contains(&next) {
+                    current.push(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            push(&mut current, Boundary::Sentence);
+        }
+
+        // 「おはよう。」「こんにちは。」のように句点で終わる括弧が続く場合も分ける。
+        // 「雨だ。」と言った、のように地の文が続く場合は分けない
+        if CLOSING_BRACKETS.contains(&c) && depth == 0 {
+            let ends_sentence = current
+                .chars()
+                .rev()
+                .nth(1)
+                .is_some_and(|prev| SENTENCE_TERMINATORS.contains(&prev));
+            let followed_by_quote = chars
+                .peek()
+                .is_some_and(|next| OPENING_BRACKETS.contains(next));
+            if ends_sentence && followed_by_quote {
+                push(&mut current, Boundary::Sentence);
+            }
+        }
+    }
+    push(&mut current, Boundary::Section);
+    if let Some(last) = sentences.last_mut() {
+        last.boundary = Boundary::Section;
+    }
+    sentences
+}
+
+/// [`VVTTSEngine::tts_document`](crate::VVTTSEngine::tts_document) のオプション
+///
+/// 無音の長さは秒で、各文の前後の無音（`pre_phoneme_length` と `post_phoneme_length`）に加えて入る。
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentOptions {
+    pub tts: TtsOptions,
+    pub sentence_pause: f32,
+    pub paragraph_pause: f32,
+    pub section_pause: f32,
+}
+
+impl Default for DocumentOptions {
+    fn default() -> Self {
+        DocumentOptions {
+            tts: TtsOptions::default(),
+            sentence_pause: 0.2,
+            paragraph_pause: 0.6,
+            section_pause: 1.2,
+        }
+    }
+}
+
+impl DocumentOptions {
+    pub fn pause_length(&self, boundary: Boundary) -> f32 {
+        match boundary {
+            Boundary::Sentence => self.sentence_pause,
+            Boundary::Paragraph => self.paragraph_pause,
+            Boundary::Section => self.section_pause,
+        }
+    }
+}
+
+/// 文ごとの進み具合
+#[derive(Debug, Clone, Copy)]
+pub struct DocumentProgress<'a> {
+    /// 合成し終えた文の番号
+    pub index: usize,
+    pub total: usize,
+    pub sentence: &'a str,
+}
+
+/// 音声の中での文の位置（秒）
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentenceSpan {
+    pub text: String,
+    pub boundary: Boundary,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TimedSpan for SentenceSpan {
+    fn start(&self) -> f32 {
+        self.start
+    }
+
+    fn end(&self) -> f32 {
+        self.end
+    }
+
+    fn set_time(&mut self, start: f32, end: f32) {
+        (self.start, self.end) = (start, end);
+    }
+}
+
+/// 文章の音声合成の結果
+#[derive(Debug, Clone)]
+pub struct DocumentResult {
+    /// 区間は文ごとの位置
+    pub audio: AudioBuffer<SentenceSpan>,
+}
+
+impl DocumentResult {
+    pub(crate) fn new(options: &DocumentOptions) -> DocumentResult {
+        let channels = if options.tts.output_stereo { 2 } else { 1 };
+        DocumentResult {
+            audio: AudioBuffer::new(Vec::new(), options.tts.output_sampling_rate, channels),
+        }
+    }
+
+    /// 文の音声と、その後ろの区切りの無音を足す
+    pub(crate) fn push(&mut self, sentence: &Sentence, result: TtsResult, pause_length: f32) {
+        self.audio
+            .push(result.audio.samples, result.audio.timings, |start, end| {
+                SentenceSpan {
+                    text: sentence.text.clone(),
+                    boundary: sentence.boundary,
+                    start,
+                    end,
+                }
+            });
+        self.audio.push_silence(pause_length);
+    }
+
+    /// 文ごとの位置
+    pub fn sentences(&self) -> &[SentenceSpan] {
+        &self.audio.spans
+    }
+}
+
+#[cfg(test)]
+mod document_tests {
+    use super::{split_sentences, Boundary};
+
+    #[test]
+    fn test_split_sentences() {
+        let text = "「雨だ。傘は？」と彼は言った。そうね……。\n\
+                    本当に！？『はい。』「いいえ。」\n\n第二章\r\n「おはよう」";
+        let sentences = split_sentences(text)
+            .into_iter()
+            .map(|s| (s.text, s.boundary))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sentences,
+            [
+                (
+                    "「雨だ。傘は？」と彼は言った。".to_string(),
+                    Boundary::Sentence
+                ),
+                ("そうね……。".to_string(), Boundary::Paragraph),
+                ("本当に！？".to_string(), Boundary::Sentence),
+                ("『はい。』".to_string(), Boundary::Sentence),
+                ("「いいえ。」".to_string(), Boundary::Section),
+                ("第二章".to_string(), Boundary::Paragraph),
+                ("「おはよう」".to_string(), Boundary::Section),
+            ]
+        );
+        assert!(split_sentences("……\n\n").is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+//! 合成した音声を各種の形式に書き出す
+//!
+//! WAV 以外の形式はそれぞれ機能フラグ `flac`、`opus`、`mp3` で有効にする。
+
+#[cfg(feature = "flac")]
+pub mod flac;
+#[cfg(feature = "mp3")]
+pub mod mp3;
+#[cfg(feature = "opus")]
+pub mod opus;
+pub mod wav;
+
+use wav::{write_wav, SampleFormat, WavSpec};
+
+/// 出力する音声の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Wav(SampleFormat),
+    /// 16 bit の可逆圧縮
+    #[cfg(feature = "flac")]
+    Flac,
+    /// `bitrate` はビット毎秒
+    #[cfg(feature = "opus")]
+    OggOpus {
+        bitrate: u32,
+    },
+    /// `bitrate` はキロビット毎秒
+    #[cfg(feature = "mp3")]
+    Mp3 {
+        bitrate: u32,
+    },
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Wav(SampleFormat::Pcm16)
+    }
+}
+
+impl OutputFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Wav(_) => "audio/wav",
+            #[cfg(feature = "flac")]
+            OutputFormat::Flac => "audio/flac",
+            #[cfg(feature = "opus")]
+            OutputFormat::OggOpus { .. } => "audio/ogg",
+            #[cfg(feature = "mp3")]
+            OutputFormat::Mp3 { .. } => "audio/mpeg",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Wav(_) => "wav",
+            #[cfg(feature = "flac")]
+            OutputFormat::Flac => "flac",
+            #[cfg(feature = "opus")]
+            OutputFormat::OggOpus { .. } => "opus",
+            #[cfg(feature = "mp3")]
+            OutputFormat::Mp3 { .. } => "mp3",
+        }
+    }
+}
+
+/// 左右のサンプルが交互に並んだ音声を指定した形式にする
+pub fn encode(
+    samples: &[f32],
+    sampling_rate: u32,
+    channels: u16,
+    format: OutputFormat,
+) -> Result<Vec<u8>, String> {
+    match format {
+        OutputFormat::Wav(sample_format) => {
+            let spec = WavSpec {
+                sampling_rate,
+                channels,
+                sample_format,
+            };
+            write_wav(Vec::new(), spec, samples).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "flac")]
+        OutputFormat::Flac => flac::encode_flac(samples, sampling_rate, channels),
+        #[cfg(feature = "opus")]
+        OutputFormat::OggOpus { bitrate } => {
+            opus::encode_ogg_opus(samples, sampling_rate, channels, bitrate)
+        }
+        #[cfg(feature = "mp3")]
+        OutputFormat::Mp3 { bitrate } => mp3::encode_mp3(samples, sampling_rate, channels, bitrate),
+    }
+}
@@ -0,0 +1,153 @@
+//! MP3
+//!
+//! 符号化には LAME を使う。
+
+use mp3lame_encoder::{
+    max_required_buffer_size, Bitrate, Builder, FlushNoGap, InterleavedPcm, MonoPcm, Quality,
+};
+
+fn lame_bitrate(kbps: u32) -> Result<Bitrate, String> {
+    Ok(match kbps {
+        8 => Bitrate::Kbps8,
+        16 => Bitrate::Kbps16,
+        24 => Bitrate::Kbps24,
+        32 => Bitrate::Kbps32,
+        40 => Bitrate::Kbps40,
+        48 => Bitrate::Kbps48,
+        64 => Bitrate::Kbps64,
+        80 => Bitrate::Kbps80,
+        96 => Bitrate::Kbps96,
+        112 => Bitrate::Kbps112,
+        128 => Bitrate::Kbps128,
+        160 => Bitrate::Kbps160,
+        192 => Bitrate::Kbps192,
+        224 => Bitrate::Kbps224,
+        256 => Bitrate::Kbps256,
+        320 => Bitrate::Kbps320,
+        _ => return Err(format!("unsupported MP3 bitrate {} kbps", kbps)),
+    })
+}
+
+/// 左右のサンプルが交互に並んだ音声を MP3 形式にする
+///
+/// `bitrate` はキロビット毎秒で、8 から 320 までの MP3 の規格にある値のみ使える。
+pub fn encode_mp3(
+    samples: &[f32],
+    sampling_rate: u32,
+    channels: u16,
+    bitrate: u32,
+) -> Result<Vec<u8>, String> {
+    if !(1..=2).contains(&channels) {
+        return Err(format!(
+            "MP3 supports 1 or 2 channels, but got {}",
+            channels
+        ));
+    }
+    let mut builder = Builder::new().ok_or("failed to create LAME encoder")?;
+    builder
+        .set_num_channels(channels as u8)
+        .map_err(|e| format!("failed to set MP3 channels: {:?}", e))?;
+    builder
+        .set_sample_rate(sampling_rate)
+        .map_err(|e| format!("failed to set MP3 sampling rate: {:?}", e))?;
+    builder
+        .set_brate(lame_bitrate(bitrate)?)
+        .map_err(|e| format!("failed to set MP3 bitrate: {:?}", e))?;
+    builder
+        .set_quality(Quality::Good)
+        .map_err(|e| format!("failed to set MP3 quality: {:?}", e))?;
+    let mut encoder = builder
+        .build()
+        .map_err(|e| format!("failed to initialize LAME encoder: {:?}", e))?;
+
+    let pcm = samples
+        .iter()
+        .map(|s| (s.clamp(-1.0, 1.0) * 32767.0).round() as i16)
+        .collect::<Vec<_>>();
+    let frames = pcm.len() / channels as usize;
+    let mut output = Vec::with_capacity(max_required_buffer_size(frames));
+    let encoded = if channels == 1 {
+        encoder.encode(MonoPcm(&pcm), output.spare_capacity_mut())
+    } else {
+        encoder.encode(InterleavedPcm(&pcm), output.spare_capacity_mut())
+    }
+    .map_err(|e| format!("failed to encode MP3: {:?}", e))?;
+    // SAFETY: LAME は先頭から encoded バイトを書き込んでいる
+    unsafe { output.set_len(encoded) };
+
+    output.reserve(7200);
+    let flushed = encoder
+        .flush::<FlushNoGap>(output.spare_capacity_mut())
+        .map_err(|e| format!("failed to flush MP3: {:?}", e))?;
+    // SAFETY: 同上
+    unsafe { output.set_len(encoded + flushed) };
+    Ok(output)
+}
+
+#[cfg(test)]
+mod mp3_tests {
+    use super::encode_mp3;
+
+    // MPEG オーディオ Layer III のフレームヘッダーから、フレームの長さ（バイト）と
+    // サンプリングレート、フレームあたりのサンプル数を読む
+    fn parse_frame_header(header: &[u8]) -> (usize, u32, usize) {
+        assert_eq!(header[0], 0xff);
+        assert_eq!(header[1] & 0xe0, 0xe0, "frame sync is missing");
+        assert_eq!((header[1] >> 1) & 0x03, 1, "not Layer III");
+        let version = (header[1] >> 3) & 0x03;
+        let bitrate_index = (header[2] >> 4) as usize;
+        let rate_index = ((header[2] >> 2) & 0x03) as usize;
+        let padding = ((header[2] >> 1) & 0x01) as usize;
+        let (bitrates, rates, samples_per_frame): (&[u32], [u32; 3], usize) = match version {
+            3 => (
+                &[
+                    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+                ],
+                [44100, 48000, 32000],
+                1152,
+            ),
+            2 => (
+                &[0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+                [22050, 24000, 16000],
+                576,
+            ),
+            0 => (
+                &[0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+                [11025, 12000, 8000],
+                576,
+            ),
+            _ => panic!("reserved MPEG version"),
+        };
+        let bitrate = bitrates[bitrate_index];
+        let rate = rates[rate_index];
+        let length = samples_per_frame / 8 * bitrate as usize * 1000 / rate as usize + padding;
+        (length, rate, samples_per_frame)
+    }
+
+    #[test]
+    fn test_encode_mp3() {
+        for (sampling_rate, channels, bitrate) in [(24000, 1, 64), (44100, 2, 128)] {
+            let frames = sampling_rate as usize / 2;
+            let samples = (0..frames * channels as usize)
+                .map(|i| 0.3 * (i as f32 / channels as f32 * 0.05).sin())
+                .collect::<Vec<_>>();
+            let mp3 = encode_mp3(&samples, sampling_rate, channels, bitrate).unwrap();
+
+            // 先頭からフレームを順にたどり、最後のバイトでちょうど終わることを確かめる
+            let mut position = 0;
+            let mut decoded_frames = 0;
+            while position < mp3.len() {
+                let (length, rate, samples_per_frame) = parse_frame_header(&mp3[position..]);
+                assert_eq!(rate, sampling_rate);
+                position += length;
+                decoded_frames += samples_per_frame;
+            }
+            assert_eq!(position, mp3.len());
+            // エンコーダーの遅延と最後のフレームの埋め合わせのぶんだけ長くなる
+            let duration = decoded_frames as f32 / sampling_rate as f32;
+            assert!((0.5..0.7).contains(&duration), "{}", duration);
+        }
+        assert!(encode_mp3(&[0.0; 100], 24000, 1, 100).is_err());
+        assert!(encode_mp3(&[0.0; 300], 24000, 3, 64).is_err());
+    }
+}
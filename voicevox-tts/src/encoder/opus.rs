@@ -0,0 +1,199 @@
+//! Ogg コンテナに入れた Opus
+//!
+//! 符号化には libopus を使う。libopus が扱えないサンプリングレートの音声は 48 kHz に変換する。
+
+use audiopus::{coder::Encoder, Application, Bitrate, Channels, SampleRate};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+
+use crate::resampler::resample_interleaved;
+
+// Ogg Opus の時刻は常に 48 kHz のサンプル数で表す
+const GRANULE_RATE: u64 = 48000;
+// 1 パケットあたり 20 ms
+const FRAMES_PER_SECOND: u32 = 50;
+const MAX_PACKET_SIZE: usize = 4000;
+const STREAM_SERIAL: u32 = 0x5656_5454;
+const VENDOR: &str = concat!("voicevox-tts ", env!("CARGO_PKG_VERSION"));
+
+fn opus_sample_rate(sampling_rate: u32) -> Option<SampleRate> {
+    match sampling_rate {
+        8000 => Some(SampleRate::Hz8000),
+        12000 => Some(SampleRate::Hz12000),
+        16000 => Some(SampleRate::Hz16000),
+        24000 => Some(SampleRate::Hz24000),
+        48000 => Some(SampleRate::Hz48000),
+        _ => None,
+    }
+}
+
+fn opus_head(channels: u16, pre_skip: u16, input_sampling_rate: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(channels as u8);
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&input_sampling_rate.to_le_bytes());
+    // 出力ゲインとチャンネルマッピング
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    head
+}
+
+fn opus_tags() -> Vec<u8> {
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    tags.extend_from_slice(VENDOR.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    tags
+}
+
+/// 左右のサンプルが交互に並んだ音声を Ogg Opus 形式にする
+///
+/// `bitrate` はビット毎秒。
+pub fn encode_ogg_opus(
+    samples: &[f32],
+    sampling_rate: u32,
+    channels: u16,
+    bitrate: u32,
+) -> Result<Vec<u8>, String> {
+    let opus_channels = match channels {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        _ => {
+            return Err(format!(
+                "Opus supports 1 or 2 channels, but got {}",
+                channels
+            ))
+        }
+    };
+    let (samples, encoder_rate) = match opus_sample_rate(sampling_rate) {
+        Some(_) => (samples.to_vec(), sampling_rate),
+        None => (
+            resample_interleaved(samples, channels as usize, sampling_rate, 48000)?,
+            48000,
+        ),
+    };
+    let opus_rate = opus_sample_rate(encoder_rate).unwrap();
+
+    let mut encoder = Encoder::new(opus_rate, opus_channels, Application::Audio)
+        .map_err(|e| format!("failed to create Opus encoder: {}", e))?;
+    encoder
+        .set_bitrate(Bitrate::BitsPerSecond(bitrate as i32))
+        .map_err(|e| format!("failed to set Opus bitrate {}: {}", bitrate, e))?;
+    let lookahead = encoder
+        .lookahead()
+        .map_err(|e| format!("failed to get Opus lookahead: {}", e))? as u64;
+
+    let to_granule = |frames: u64| frames * GRANULE_RATE / encoder_rate as u64;
+    let pre_skip = to_granule(lookahead);
+    let channels = channels as usize;
+    let total_frames = (samples.len() / channels) as u64;
+    let frame_size = (encoder_rate / FRAMES_PER_SECOND) as usize;
+
+    // 先読みのぶん後ろに無音を足し、最後のパケットまで埋める
+    let mut padded = samples;
+    let padded_frames =
+        (total_frames + lookahead).div_ceil(frame_size as u64) as usize * frame_size;
+    padded.resize(padded_frames * channels, 0.0);
+
+    let mut writer = PacketWriter::new(Vec::new());
+    let write_error = |e: std::io::Error| format!("failed to write Ogg page: {}", e);
+    writer
+        .write_packet(
+            opus_head(channels as u16, pre_skip as u16, sampling_rate).into_boxed_slice(),
+            STREAM_SERIAL,
+            PacketWriteEndInfo::EndPage,
+            0,
+        )
+        .map_err(write_error)?;
+    writer
+        .write_packet(
+            opus_tags().into_boxed_slice(),
+            STREAM_SERIAL,
+            PacketWriteEndInfo::EndPage,
+            0,
+        )
+        .map_err(write_error)?;
+
+    let end_granule = pre_skip + to_granule(total_frames);
+    let mut output = vec![0; MAX_PACKET_SIZE];
+    let packets = padded.chunks(frame_size * channels).collect::<Vec<_>>();
+    for (i, packet) in packets.iter().enumerate() {
+        let len = encoder
+            .encode_float(packet, &mut output)
+            .map_err(|e| format!("failed to encode Opus packet: {}", e))?;
+        let is_last = i + 1 == packets.len();
+        let granule = if is_last {
+            end_granule
+        } else {
+            to_granule(((i + 1) * frame_size) as u64)
+        };
+        writer
+            .write_packet(
+                output[..len].to_vec().into_boxed_slice(),
+                STREAM_SERIAL,
+                if is_last {
+                    PacketWriteEndInfo::EndStream
+                } else {
+                    PacketWriteEndInfo::NormalPacket
+                },
+                granule,
+            )
+            .map_err(write_error)?;
+    }
+    Ok(writer.into_inner())
+}
+
+#[cfg(test)]
+mod opus_tests {
+    use std::io::Cursor;
+
+    use ogg::reading::PacketReader;
+
+    use super::{encode_ogg_opus, GRANULE_RATE};
+
+    #[test]
+    fn test_encode_ogg_opus() {
+        // 24 kHz はそのまま、44.1 kHz は 48 kHz に変換して符号化される
+        for (sampling_rate, channels) in [(24000u32, 1u16), (44100, 2)] {
+            let frames = sampling_rate as usize / 2;
+            let samples = (0..frames * channels as usize)
+                .map(|i| 0.3 * (i as f32 / channels as f32 * 0.05).sin())
+                .collect::<Vec<_>>();
+            let data = encode_ogg_opus(&samples, sampling_rate, channels, 32000).unwrap();
+
+            let mut reader = PacketReader::new(Cursor::new(data));
+            let head = reader.read_packet().unwrap().unwrap();
+            assert!(head.first_in_stream() && head.last_in_page());
+            assert_eq!(&head.data[..8], b"OpusHead");
+            assert_eq!(head.data[8], 1);
+            assert_eq!(head.data[9], channels as u8);
+            let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+            let input_rate = u32::from_le_bytes(head.data[12..16].try_into().unwrap());
+            assert_eq!(input_rate, sampling_rate);
+            assert_eq!(head.absgp_page(), 0);
+
+            let tags = reader.read_packet().unwrap().unwrap();
+            assert!(tags.last_in_page());
+            assert_eq!(&tags.data[..8], b"OpusTags");
+            assert_eq!(tags.absgp_page(), 0);
+
+            // 音声のページの位置は増えていき、最後のページは先読みと音声の長さの和になる
+            let mut granule = 0;
+            let mut last = None;
+            while let Some(packet) = reader.read_packet().unwrap() {
+                assert!(packet.absgp_page() >= granule);
+                granule = packet.absgp_page();
+                last = Some(packet);
+            }
+            let last = last.unwrap();
+            assert!(last.last_in_stream());
+            let expected = pre_skip + frames as u64 * GRANULE_RATE / sampling_rate as u64;
+            assert!(
+                last.absgp_page().abs_diff(expected) <= 1,
+                "{} {}",
+                last.absgp_page(),
+                expected
+            );
+        }
+    }
+}
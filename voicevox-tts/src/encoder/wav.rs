@@ -0,0 +1,338 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_MULAW: u16 = 7;
+
+// 長さの分からないストリームでは、チャンクのサイズをこの値にしておく
+const UNKNOWN_SIZE: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 16 bit 整数 PCM
+    Pcm16,
+    /// 24 bit 整数 PCM
+    Pcm24,
+    /// 32 bit 浮動小数点数
+    Float32,
+    /// 8 bit μ-law（G.711）
+    MuLaw,
+}
+
+impl SampleFormat {
+    fn bytes_per_sample(self) -> u16 {
+        match self {
+            SampleFormat::Pcm16 => 2,
+            SampleFormat::Pcm24 => 3,
+            SampleFormat::Float32 => 4,
+            SampleFormat::MuLaw => 1,
+        }
+    }
+
+    fn format_tag(self) -> u16 {
+        match self {
+            SampleFormat::Pcm16 | SampleFormat::Pcm24 => WAVE_FORMAT_PCM,
+            SampleFormat::Float32 => WAVE_FORMAT_IEEE_FLOAT,
+            SampleFormat::MuLaw => WAVE_FORMAT_MULAW,
+        }
+    }
+
+    // PCM 以外の形式では、fmt チャンクの拡張部分のサイズと fact チャンクが必要
+    fn is_extensible(self) -> bool {
+        self.format_tag() != WAVE_FORMAT_PCM
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub sampling_rate: u32,
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+}
+
+impl WavSpec {
+    fn header_size(&self) -> u64 {
+        if self.sample_format.is_extensible() {
+            58
+        } else {
+            44
+        }
+    }
+}
+
+/// WAV 形式で書き出す
+///
+/// サンプルは -1.0 から 1.0 の範囲の `f32` で受け取り、ステレオの場合は左右を交互に並べる。
+/// 整数の形式では範囲外の値を丸める。
+pub struct WavWriter<W: Write> {
+    writer: W,
+    spec: WavSpec,
+    num_samples: Option<u64>,
+    written_samples: u64,
+}
+
+impl<W: Write> WavWriter<W> {
+    /// ヘッダを書き込む
+    ///
+    /// `num_samples` には全チャンネル分のサンプル数を指定する。
+    /// `None` の場合はサイズを不明としてヘッダを書くので、
+    /// シーク可能な書き出し先なら [`WavWriter::finish_seekable`] で正しいサイズに直せる。
+    pub fn new(writer: W, spec: WavSpec, num_samples: Option<u64>) -> io::Result<WavWriter<W>> {
+        if spec.channels == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "channels must be positive",
+            ));
+        }
+        if let Some(num_samples) = num_samples {
+            if !num_samples.is_multiple_of(spec.channels as u64) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "num_samples must be a multiple of channels",
+                ));
+            }
+            if spec.header_size() + data_chunk_size(&spec, num_samples) > u32::MAX as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "audio is too long for WAV",
+                ));
+            }
+        }
+        let mut wav_writer = WavWriter {
+            writer,
+            spec,
+            num_samples,
+            written_samples: 0,
+        };
+        wav_writer.write_header(num_samples)?;
+        Ok(wav_writer)
+    }
+
+    fn write_header(&mut self, num_samples: Option<u64>) -> io::Result<()> {
+        let spec = self.spec;
+        let (riff_size, fact_size, data_size) = match num_samples {
+            Some(num_samples) => {
+                let data_size = data_chunk_size(&spec, num_samples);
+                (
+                    (spec.header_size() - 8 + data_size) as u32,
+                    (num_samples / spec.channels as u64) as u32,
+                    (num_samples * spec.sample_format.bytes_per_sample() as u64) as u32,
+                )
+            }
+            None => (UNKNOWN_SIZE, UNKNOWN_SIZE, UNKNOWN_SIZE),
+        };
+        let block_align = spec.channels * spec.sample_format.bytes_per_sample();
+
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&riff_size.to_le_bytes())?;
+        w.write_all(b"WAVE")?;
+
+        w.write_all(b"fmt ")?;
+        let fmt_size: u32 = if spec.sample_format.is_extensible() {
+            18
+        } else {
+            16
+        };
+        w.write_all(&fmt_size.to_le_bytes())?;
+        w.write_all(&spec.sample_format.format_tag().to_le_bytes())?;
+        w.write_all(&spec.channels.to_le_bytes())?;
+        w.write_all(&spec.sampling_rate.to_le_bytes())?;
+        w.write_all(&(spec.sampling_rate * block_align as u32).to_le_bytes())?;
+        w.write_all(&block_align.to_le_bytes())?;
+        w.write_all(&(spec.sample_format.bytes_per_sample() * 8).to_le_bytes())?;
+        if spec.sample_format.is_extensible() {
+            w.write_all(&0u16.to_le_bytes())?;
+            w.write_all(b"fact")?;
+            w.write_all(&4u32.to_le_bytes())?;
+            w.write_all(&fact_size.to_le_bytes())?;
+        }
+
+        w.write_all(b"data")?;
+        w.write_all(&data_size.to_le_bytes())
+    }
+
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        if let Some(num_samples) = self.num_samples {
+            if self.written_samples + samples.len() as u64 > num_samples {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "more samples than declared in the header",
+                ));
+            }
+        }
+        let mut buf =
+            Vec::with_capacity(samples.len() * self.spec.sample_format.bytes_per_sample() as usize);
+        for &sample in samples {
+            match self.spec.sample_format {
+                SampleFormat::Pcm16 => buf.extend_from_slice(&to_i16(sample).to_le_bytes()),
+                SampleFormat::Pcm24 => {
+                    let value = (sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32;
+                    buf.extend_from_slice(&value.to_le_bytes()[..3]);
+                }
+                SampleFormat::Float32 => buf.extend_from_slice(&sample.to_le_bytes()),
+                SampleFormat::MuLaw => buf.push(linear_to_mulaw(to_i16(sample))),
+            }
+        }
+        self.writer.write_all(&buf)?;
+        self.written_samples += samples.len() as u64;
+        Ok(())
+    }
+
+    fn write_padding(&mut self) -> io::Result<()> {
+        // チャンクは偶数バイトに揃える
+        let data_size = self.written_samples * self.spec.sample_format.bytes_per_sample() as u64;
+        if data_size % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        Ok(())
+    }
+
+    /// 書き込みを終えて、書き出し先を返す
+    ///
+    /// ヘッダにサンプル数を指定した場合は、その数だけ書き込んである必要がある。
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(num_samples) = self.num_samples {
+            if self.written_samples != num_samples {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} samples are declared in the header, but {} samples are written",
+                        num_samples, self.written_samples
+                    ),
+                ));
+            }
+        }
+        self.write_padding()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// 書き込みを終えて、ヘッダのサイズを実際に書き込んだサンプル数に直す
+    pub fn finish_seekable(mut self) -> io::Result<W> {
+        if !self
+            .written_samples
+            .is_multiple_of(self.spec.channels as u64)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "written samples must be a multiple of channels",
+            ));
+        }
+        self.write_padding()?;
+        let end = self.writer.stream_position()?;
+        let start =
+            end - self.spec.header_size() - data_chunk_size(&self.spec, self.written_samples);
+        self.writer.seek(SeekFrom::Start(start))?;
+        self.write_header(Some(self.written_samples))?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// サンプル全体を WAV 形式で書き出す
+pub fn write_wav<W: Write>(writer: W, spec: WavSpec, samples: &[f32]) -> io::Result<W> {
+    let mut wav_writer = WavWriter::new(writer, spec, Some(samples.len() as u64))?;
+    wav_writer.write_samples(samples)?;
+    wav_writer.finish()
+}
+
+// data チャンクの中身のサイズ（埋め草を含む）
+fn data_chunk_size(spec: &WavSpec, num_samples: u64) -> u64 {
+    let size = num_samples * spec.sample_format.bytes_per_sample() as u64;
+    size + size % 2
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * 32767.0).round() as i16
+}
+
+// ITU-T G.711 の μ-law 変換
+fn linear_to_mulaw(sample: i16) -> u8 {
+    const BIAS: i32 = 0x84;
+    const CLIP: i32 = 32635;
+
+    let sign = if sample < 0 { 0x80 } else { 0 };
+    let magnitude = (sample as i32).abs().min(CLIP) + BIAS;
+    let exponent = (7 - (magnitude << 17).leading_zeros().min(7)) as i32;
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0f;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+#[cfg(test)]
+mod wav_tests {
+    use std::io::Cursor;
+
+    use super::{write_wav, SampleFormat, WavSpec, WavWriter};
+
+    fn samples() -> Vec<f32> {
+        (0..301)
+            .flat_map(|i| {
+                let value = (i as f32 * 0.05).sin() * 0.8;
+                [value, -value]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_with_hound() {
+        let samples = samples();
+        for sample_format in [
+            SampleFormat::Pcm16,
+            SampleFormat::Pcm24,
+            SampleFormat::Float32,
+        ] {
+            let spec = WavSpec {
+                sampling_rate: 44100,
+                channels: 2,
+                sample_format,
+            };
+            let wav = write_wav(Vec::new(), spec, &samples).unwrap();
+            let mut reader = hound::WavReader::new(Cursor::new(&wav)).unwrap();
+            assert_eq!(reader.spec().channels, 2);
+            assert_eq!(reader.spec().sample_rate, 44100);
+            assert_eq!(reader.len() as usize, samples.len());
+            let read = match sample_format {
+                SampleFormat::Float32 => reader.samples::<f32>().map(|s| s.unwrap()).collect(),
+                SampleFormat::Pcm16 => reader
+                    .samples::<i32>()
+                    .map(|s| s.unwrap() as f32 / 32767.0)
+                    .collect(),
+                _ => reader
+                    .samples::<i32>()
+                    .map(|s| s.unwrap() as f32 / 8_388_607.0)
+                    .collect::<Vec<_>>(),
+            };
+            for (a, b) in samples.iter().zip(read.iter()) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_and_mulaw() {
+        let samples = samples();
+        let spec = WavSpec {
+            sampling_rate: 8000,
+            channels: 1,
+            sample_format: SampleFormat::MuLaw,
+        };
+        let mut wav_writer = WavWriter::new(Cursor::new(Vec::new()), spec, None).unwrap();
+        wav_writer.write_samples(&samples[..100]).unwrap();
+        wav_writer.write_samples(&samples[100..301]).unwrap();
+        let wav = wav_writer.finish_seekable().unwrap().into_inner();
+
+        // 奇数バイトの data チャンクには埋め草が入る
+        assert_eq!(wav.len(), 58 + 302);
+        assert_eq!(&wav[4..8], &(58 - 8 + 302u32).to_le_bytes());
+        assert_eq!(&wav[46..50], &301u32.to_le_bytes());
+        assert_eq!(&wav[54..58], &301u32.to_le_bytes());
+        // 無音は 0xff、正負で符号ビットが変わる
+        assert_eq!(super::linear_to_mulaw(0), 0xff);
+        assert_eq!(super::linear_to_mulaw(32767), 0x80);
+        assert_eq!(super::linear_to_mulaw(-32768), 0x00);
+    }
+}
@@ -0,0 +1,200 @@
+use std::borrow::Cow;
+
+use lindera::{dictionary::load_dictionary, mode::Mode, segmenter::Segmenter};
+
+use super::{make_pause_mora, Frontend, Word};
+use crate::{
+    model::{AccentPhraseModel, MoraModel},
+    mora_list::kana2moras,
+};
+
+/// Lindera による pure Rust の日本語処理部
+///
+/// IPADIC のような MeCab 形式の辞書はアクセントの情報を持たないため、
+/// アクセント核の位置は自立語の品詞による簡単な規則で決める。
+/// OpenJTalk とはアクセントが異なることがある。
+/// 読みをモーラに分けられない語（辞書に無い英単語など）があるとエラーになる。
+pub struct LinderaFrontend {
+    segmenter: Segmenter,
+}
+
+impl LinderaFrontend {
+    /// `dictionary` には `embedded://ipadic` のような URI か、辞書のディレクトリのパスを指定する
+    pub fn new(dictionary: &str) -> Result<LinderaFrontend, String> {
+        let dictionary = load_dictionary(dictionary).map_err(|e| e.to_string())?;
+        Ok(LinderaFrontend {
+            segmenter: Segmenter::new(Mode::Normal, dictionary, None),
+        })
+    }
+}
+
+struct PhraseBuilder {
+    moras: Vec<MoraModel>,
+    // 0 のときは平板型として、アクセント句の最後のモーラをアクセント核とする
+    accent: u32,
+}
+
+impl PhraseBuilder {
+    fn build(self) -> AccentPhraseModel {
+        let accent = if self.accent == 0 {
+            self.moras.len() as u32
+        } else {
+            self.accent
+        };
+        AccentPhraseModel {
+            moras: self.moras,
+            accent,
+            pause_mora: None,
+            is_interrogative: false,
+        }
+    }
+}
+
+fn content_word_accent(pos: &str, mora_size: usize) -> u32 {
+    match pos {
+        // 動詞・形容詞は後ろから二番目のモーラを核とする（「たべる」「たかい」など）
+        "動詞" | "形容詞" if mora_size >= 2 => (mora_size - 1) as u32,
+        "動詞" | "形容詞" | "副詞" | "感動詞" | "接続詞" => 1,
+        _ => 0,
+    }
+}
+
+// 発音、読み、表層形の順に、モーラに分けられるものを使う
+fn token_moras(details: &[String], surface: &str) -> Result<Vec<MoraModel>, String> {
+    [
+        details.get(8).map(|pron| pron.as_str()),
+        details.get(7).map(|read| read.as_str()),
+        Some(surface),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|pron| *pron != "*")
+    .find_map(|pron| kana2moras(pron).ok().filter(|moras| !moras.is_empty()))
+    .ok_or_else(|| {
+        format!(
+            "couldn't read `{}`: its reading can't be split into moras",
+            surface
+        )
+    })
+}
+
+impl Frontend for LinderaFrontend {
+    fn create_accent_phrases(&self, text: &str) -> Result<Vec<AccentPhraseModel>, String> {
+        Ok(self.analyze(text)?.0)
+    }
+
+    fn words(&self, text: &str) -> Result<Vec<Word>, String> {
+        Ok(self.analyze(text)?.1)
+    }
+
+    fn analyze(&self, text: &str) -> Result<(Vec<AccentPhraseModel>, Vec<Word>), String> {
+        let tokens = self
+            .segmenter
+            .segment(Cow::Borrowed(text))
+            .map_err(|e| e.to_string())?;
+
+        let mut accent_phrases: Vec<AccentPhraseModel> = Vec::new();
+        let mut words = Vec::new();
+        let mut builder: Option<PhraseBuilder> = None;
+
+        for mut token in tokens {
+            let surface = token.surface.to_string();
+            let details = token
+                .details()
+                .iter()
+                .map(|detail| detail.to_string())
+                .collect::<Vec<_>>();
+            let pos = details.first().map(|pos| pos.as_str()).unwrap_or("*");
+            let pos_group1 = details.get(1).map(|pos| pos.as_str()).unwrap_or("*");
+
+            if pos == "記号" {
+                let is_interrogative = surface.contains('？') || surface.contains('?');
+                if is_interrogative || matches!(pos_group1, "句点" | "読点") {
+                    if let Some(builder) = builder.take() {
+                        accent_phrases.push(builder.build());
+                    }
+                    if let Some(last) = accent_phrases.last_mut() {
+                        last.is_interrogative |= is_interrogative;
+                        last.pause_mora = Some(make_pause_mora());
+                    }
+                }
+                continue;
+            }
+
+            let mut moras = token_moras(&details, &surface)?;
+            words.push(Word {
+                text: surface,
+                mora_count: moras.len(),
+            });
+
+            let is_attached = matches!(pos, "助詞" | "助動詞")
+                || pos_group1 == "接尾"
+                || (pos == "動詞" && pos_group1 == "非自立");
+            match builder.as_mut() {
+                Some(builder) if is_attached => builder.moras.append(&mut moras),
+                _ => {
+                    if let Some(builder) = builder.take() {
+                        accent_phrases.push(builder.build());
+                    }
+                    builder = Some(PhraseBuilder {
+                        accent: content_word_accent(pos, moras.len()),
+                        moras,
+                    });
+                }
+            }
+        }
+        if let Some(builder) = builder.take() {
+            accent_phrases.push(builder.build());
+        }
+        // OpenJTalk と同様に、最後のアクセント句の後ろにはポーズを置かない
+        if let Some(last) = accent_phrases.last_mut() {
+            last.pause_mora = None;
+        }
+
+        Ok((accent_phrases, words))
+    }
+}
+
+#[cfg(test)]
+mod lindera_tests {
+    use super::LinderaFrontend;
+    use crate::frontend::Frontend;
+
+    #[test]
+    fn test_create_accent_phrases() {
+        let frontend = LinderaFrontend::new("embedded://ipadic").unwrap();
+        let accent_phrases = frontend.create_accent_phrases("今日は雨が降る。").unwrap();
+
+        let vowels = accent_phrases
+            .iter()
+            .map(|accent_phrase| {
+                accent_phrase
+                    .moras
+                    .iter()
+                    .map(|mora| mora.vowel.as_str())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vowels, ["ooa", "aea", "uu"]);
+        // 名詞で始まる句は平板型、動詞は後ろから二番目のモーラが核になる
+        let accents = accent_phrases
+            .iter()
+            .map(|accent_phrase| accent_phrase.accent)
+            .collect::<Vec<_>>();
+        assert_eq!(accents, [3, 3, 1]);
+        assert!(accent_phrases
+            .iter()
+            .all(|accent_phrase| accent_phrase.pause_mora.is_none()));
+
+        let mora_counts = frontend
+            .words("今日は雨が降る。")
+            .unwrap()
+            .iter()
+            .map(|word| word.mora_count)
+            .collect::<Vec<_>>();
+        assert_eq!(mora_counts, [2, 1, 2, 1, 2]);
+
+        let err = frontend.create_accent_phrases("ＸＹＺ").unwrap_err();
+        assert!(err.contains("ＸＹＺ"), "{}", err);
+    }
+}
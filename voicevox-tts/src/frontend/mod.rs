@@ -0,0 +1,113 @@
+#[cfg(feature = "lindera")]
+pub mod lindera;
+pub mod openjtalk;
+
+use crate::{
+    full_context_label::utterance::Utterance,
+    model::{AccentPhraseModel, MoraModel},
+    mora_list::mora2text,
+};
+
+/// テキストを解析してアクセント句を作る日本語処理部
+///
+/// 作られるアクセント句の音素長と音高は 0 のままでよく、
+/// [`SynthesisEngine`](crate::synthesis_engine::SynthesisEngine) が音声合成モデルで埋める。
+pub trait Frontend {
+    fn create_accent_phrases(&self, text: &str) -> Result<Vec<AccentPhraseModel>, String>;
+
+    /// テキストを単語に分ける。単語ごとの時刻を求めるのに使い、
+    /// 単語のモーラ数の並びは [`create_accent_phrases`](Self::create_accent_phrases) のモーラと合わせる
+    fn words(&self, _text: &str) -> Result<Vec<Word>, String> {
+        Ok(Vec::new())
+    }
+
+    /// テキストからアクセント句と単語を作る
+    ///
+    /// 既定では二つを別々に求める。一度の解析で両方が得られる場合は上書きし、
+    /// 単語のモーラ数の合計がアクセント句のモーラ数と必ず合うようにする。
+    fn analyze(&self, text: &str) -> Result<(Vec<AccentPhraseModel>, Vec<Word>), String> {
+        Ok((self.create_accent_phrases(text)?, self.words(text)?))
+    }
+}
+
+/// 日本語処理部が分けた単語と、そのモーラ数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub text: String,
+    pub mora_count: usize,
+}
+
+pub(crate) fn make_pause_mora() -> MoraModel {
+    MoraModel {
+        text: "、".to_string(),
+        consonant: None,
+        consonant_length: None,
+        vowel: "pau".to_string(),
+        vowel_length: 0.0,
+        pitch: 0.0,
+    }
+}
+
+/// フルコンテキストラベルから作った発話をアクセント句のリストに変換する
+pub fn utterance_to_accent_phrases(utterance: &Utterance) -> Vec<AccentPhraseModel> {
+    utterance
+        .breath_groups
+        .iter()
+        .enumerate()
+        .fold(Vec::new(), |mut acc_vec, (i, breath_group)| {
+            acc_vec.append(
+                &mut breath_group
+                    .accent_phrases
+                    .iter()
+                    .enumerate()
+                    .map(|(j, accent_phrase)| {
+                        let moras = accent_phrase
+                            .moras
+                            .iter()
+                            .map(|mora| {
+                                let mut moras_text = mora
+                                    .phonemes()
+                                    .iter()
+                                    .map(|phoneme| phoneme.phoneme())
+                                    .collect::<Vec<_>>()
+                                    .join("");
+                                moras_text = moras_text.to_lowercase();
+                                if moras_text == "n" {
+                                    moras_text = String::from("N");
+                                }
+                                let (consonant, consonant_length) =
+                                    if let Some(ref consonant) = mora.consonant {
+                                        (Some(consonant.phoneme()), Some(0.0))
+                                    } else {
+                                        (None, None)
+                                    };
+                                MoraModel {
+                                    text: mora2text(moras_text),
+                                    consonant,
+                                    consonant_length,
+                                    vowel: mora.vowel.phoneme(),
+                                    vowel_length: 0.0,
+                                    pitch: 0.0,
+                                }
+                            })
+                            .collect();
+
+                        let pause_mora = if i != utterance.breath_groups.len() - 1
+                            && j == breath_group.accent_phrases.len() - 1
+                        {
+                            Some(make_pause_mora())
+                        } else {
+                            None
+                        };
+                        AccentPhraseModel {
+                            moras,
+                            accent: accent_phrase.accent,
+                            pause_mora,
+                            is_interrogative: accent_phrase.is_interrogative,
+                        }
+                    })
+                    .collect(),
+            );
+            acc_vec
+        })
+}
@@ -0,0 +1,105 @@
+use openjtalk::{NjdFeature, OpenJTalk};
+
+use super::{utterance_to_accent_phrases, Frontend, Word};
+use crate::{
+    full_context_label::{extract_fullcontext, extract_fullcontext_from_features},
+    model::AccentPhraseModel,
+    rewrite_rule::{FiredRule, RewriteRules},
+};
+
+/// OpenJTalk による日本語処理部
+pub struct OpenJTalkFrontend {
+    openjtalk: OpenJTalk,
+    rewrite_rules: RewriteRules,
+}
+
+impl OpenJTalkFrontend {
+    pub fn new(openjtalk: OpenJTalk) -> OpenJTalkFrontend {
+        OpenJTalkFrontend {
+            openjtalk,
+            rewrite_rules: RewriteRules::new(),
+        }
+    }
+
+    pub fn openjtalk(&self) -> OpenJTalk {
+        self.openjtalk
+    }
+
+    /// 発音を付けた直後の NJD の素性へ適用する書き換え規則を設定する
+    pub fn set_rewrite_rules(&mut self, rewrite_rules: RewriteRules) {
+        self.rewrite_rules = rewrite_rules;
+    }
+
+    pub fn has_rewrite_rules(&self) -> bool {
+        !self.rewrite_rules.is_empty()
+    }
+
+    /// テキストを解析して書き換え規則を適用し、適用後の素性と適用された規則を返す
+    ///
+    /// 規則は発音を付けた直後の素性に適用し、アクセント句やアクセント型はその後に求める。
+    pub fn apply_rewrite_rules(
+        &self,
+        text: &str,
+    ) -> Result<(Vec<NjdFeature>, Vec<FiredRule>), String> {
+        let mut features = self.openjtalk.run_pronunciation(text)?;
+        let fired_rules = self.rewrite_rules.apply(&mut features)?;
+        let features = self.openjtalk.complete_frontend(&features)?;
+        Ok((features, fired_rules))
+    }
+
+    /// テキストを解析し、書き換え規則があれば適用した NJD の素性を返す
+    fn features(&self, text: &str) -> Result<Vec<NjdFeature>, String> {
+        if self.has_rewrite_rules() {
+            Ok(self.apply_rewrite_rules(text)?.0)
+        } else {
+            self.openjtalk.run_frontend(text)
+        }
+    }
+
+    /// [`OpenJTalk::run_frontend_nbest`] などで得た NJD の素性からアクセント句を作る
+    ///
+    /// OpenJTalk が素性を読めない場合はエラーにする。
+    pub fn create_accent_phrases_from_features(
+        &self,
+        features: &[NjdFeature],
+    ) -> Result<Vec<AccentPhraseModel>, String> {
+        if features.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let utterance = extract_fullcontext_from_features(self.openjtalk, features)?;
+        Ok(utterance_to_accent_phrases(&utterance))
+    }
+}
+
+impl Frontend for OpenJTalkFrontend {
+    fn create_accent_phrases(&self, text: &str) -> Result<Vec<AccentPhraseModel>, String> {
+        if self.has_rewrite_rules() {
+            let (features, _) = self.apply_rewrite_rules(text)?;
+            return self.create_accent_phrases_from_features(&features);
+        }
+
+        let utterance = extract_fullcontext(self.openjtalk, text.to_string())?;
+        Ok(utterance_to_accent_phrases(&utterance))
+    }
+
+    fn words(&self, text: &str) -> Result<Vec<Word>, String> {
+        Ok(features_to_words(self.features(text)?))
+    }
+
+    fn analyze(&self, text: &str) -> Result<(Vec<AccentPhraseModel>, Vec<Word>), String> {
+        let features = self.features(text)?;
+        let accent_phrases = self.create_accent_phrases_from_features(&features)?;
+        Ok((accent_phrases, features_to_words(features)))
+    }
+}
+
+fn features_to_words(features: Vec<NjdFeature>) -> Vec<Word> {
+    features
+        .into_iter()
+        .map(|feature| Word {
+            text: feature.string,
+            mora_count: feature.mora_size.max(0) as usize,
+        })
+        .collect()
+}
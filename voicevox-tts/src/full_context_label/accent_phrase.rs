@@ -1,4 +1,5 @@
 use super::{mora::Mora, phoneme::Phoneme};
+use crate::model::merge_accent_phrase;
 
 #[derive(Debug, Clone)]
 pub struct AccentPhrase {
@@ -62,6 +63,18 @@ impl AccentPhrase {
         })
     }
 
+    /// ラベルのアクセント位置がモーラ数を超えていたため、
+    /// VOICEVOX/voicevox_engine#55 の回避策で丸められたかどうか
+    pub fn is_accent_clamped(&self) -> bool {
+        self.moras[0]
+            .vowel
+            .contexts
+            .get("f2")
+            .and_then(|f2| f2.parse::<usize>().ok())
+            .filter(|accent| *accent > self.moras.len())
+            .is_some()
+    }
+
     pub fn set_context(&mut self, key: String, value: String) {
         for mora in self.moras.iter_mut() {
             mora.set_context(key.clone(), value.clone());
@@ -84,9 +97,10 @@ impl AccentPhrase {
     }
 
     pub fn merge(&self, accent_phrase: &AccentPhrase) -> AccentPhrase {
-        let mut moras = Vec::new();
-        moras.append(&mut self.moras.clone());
-        moras.append(&mut accent_phrase.moras.clone());
-        AccentPhrase::new(moras, self.accent, self.is_interrogative)
+        let (moras, accent) = merge_accent_phrase(
+            (&self.moras, self.accent),
+            (&accent_phrase.moras, accent_phrase.accent),
+        );
+        AccentPhrase::new(moras, accent, self.is_interrogative)
     }
 }
@@ -4,12 +4,22 @@ pub mod mora;
 pub mod phoneme;
 pub mod utterance;
 
-use openjtalk::OpenJTalk;
+use openjtalk::{NjdFeature, OpenJTalk};
 use phoneme::Phoneme;
 use utterance::Utterance;
 
 pub fn extract_fullcontext(openjtalk: OpenJTalk, text: String) -> Result<Utterance, String> {
-    let labels = openjtalk.extract_fullcontext(text);
+    utterance_from_labels(openjtalk.extract_fullcontext(text))
+}
+
+pub fn extract_fullcontext_from_features(
+    openjtalk: OpenJTalk,
+    features: &[NjdFeature],
+) -> Result<Utterance, String> {
+    utterance_from_labels(openjtalk.make_label(features)?)
+}
+
+fn utterance_from_labels(labels: Vec<String>) -> Result<Utterance, String> {
     let phonemes = labels
         .into_iter()
         .map(|label| Phoneme::from_label(label).unwrap())
@@ -0,0 +1,282 @@
+//! テキストに直接書ける読みとアクセントの指定
+//!
+//! - `{漢字|かんじ}` は読みを指定する。読みは日本語処理部に渡す前にテキストへ差し込む
+//! - `{東京|ト'ーキョー}` のように `'` を付けると、その読みを含むアクセント句のアクセント核を指定する
+//! - `/` はその位置でアクセント句を分ける
+//! - `[pause:500ms]` はその位置に指定した長さのポーズを入れる
+//!
+//! 記号そのものを書く場合は `\/` のように `\` を前に付ける。
+
+use crate::{
+    model::{AccentPhraseModel, MoraModel},
+    mora_list::{hiragana2katakana, kana2moras},
+    ssml::parse_time,
+};
+
+/// 読みの指定。`accent` はアクセント核の位置で、読みの先頭のモーラを 1 とする
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub base: String,
+    pub kana: String,
+    pub accent: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkupToken {
+    Text(String),
+    Reading(Reading),
+    PhraseBreak,
+    /// ポーズ（秒）
+    Pause(f32),
+}
+
+/// 区切りの記号で分けた、日本語処理部に一度に渡すテキスト
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkupChunk {
+    /// 読みを差し込んだテキスト
+    pub text: String,
+    pub readings: Vec<Reading>,
+    /// 後ろに入れるポーズ（秒）
+    pub pause_after: Option<f32>,
+}
+
+/// `ト'ーキョー` を `トーキョー` とアクセント核の位置にする
+fn parse_reading(base: &str, reading: &str) -> Result<Reading, String> {
+    let accent = match reading.split_once('\'') {
+        Some((before, after)) => {
+            if after.contains('\'') {
+                return Err(format!("reading `{}` has more than one accent", reading));
+            }
+            let accent = kana2moras(before)?.len() as u32;
+            if accent == 0 {
+                return Err(format!("accent must follow a mora in `{}`", reading));
+            }
+            Some(accent)
+        }
+        None => None,
+    };
+    let kana = hiragana2katakana(&reading.replace('\'', ""));
+    if kana2moras(&kana)?.is_empty() {
+        return Err(format!("reading of `{}` is empty", base));
+    }
+    Ok(Reading {
+        base: base.to_string(),
+        kana,
+        accent,
+    })
+}
+
+pub fn parse_markup(text: &str) -> Result<Vec<MarkupToken>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+    let flush = |current: &mut String, tokens: &mut Vec<MarkupToken>| {
+        if !current.is_empty() {
+            tokens.push(MarkupToken::Text(std::mem::take(current)));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            '/' => {
+                flush(&mut current, &mut tokens);
+                tokens.push(MarkupToken::PhraseBreak);
+            }
+            '{' => {
+                let inner = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                let (base, reading) = inner
+                    .split_once('|')
+                    .ok_or_else(|| format!("expected `{{base|reading}}`, but got `{{{}`", inner))?;
+                flush(&mut current, &mut tokens);
+                tokens.push(MarkupToken::Reading(parse_reading(base, reading)?));
+            }
+            '[' => {
+                let inner = chars.by_ref().take_while(|c| *c != ']').collect::<String>();
+                match inner.strip_prefix("pause:") {
+                    Some(time) => {
+                        flush(&mut current, &mut tokens);
+                        tokens.push(MarkupToken::Pause(parse_time(time)?));
+                    }
+                    None => return Err(format!("unknown command `[{}]`", inner)),
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    flush(&mut current, &mut tokens);
+    Ok(tokens)
+}
+
+/// 区切りの記号とポーズの位置でテキストを分ける
+pub fn split_chunks(tokens: Vec<MarkupToken>) -> Vec<MarkupChunk> {
+    let mut chunks = Vec::new();
+    let mut chunk = MarkupChunk {
+        text: String::new(),
+        readings: Vec::new(),
+        pause_after: None,
+    };
+    for token in tokens {
+        match token {
+            MarkupToken::Text(text) => chunk.text.push_str(&text),
+            MarkupToken::Reading(reading) => {
+                chunk.text.push_str(&reading.kana);
+                chunk.readings.push(reading);
+            }
+            MarkupToken::PhraseBreak | MarkupToken::Pause(_) => {
+                if let MarkupToken::Pause(length) = token {
+                    chunk.pause_after = Some(length);
+                }
+                chunks.push(std::mem::replace(
+                    &mut chunk,
+                    MarkupChunk {
+                        text: String::new(),
+                        readings: Vec::new(),
+                        pause_after: None,
+                    },
+                ));
+            }
+        }
+    }
+    chunks.push(chunk);
+    chunks
+}
+
+// 無声化の違いを無視して比べるための、子音と小文字の母音
+fn mora_key(mora: &MoraModel) -> (Option<String>, String) {
+    (mora.consonant.clone(), mora.vowel.to_lowercase())
+}
+
+/// 日本語処理部が作ったアクセント句に、読みで指定したアクセント核を反映する
+///
+/// 読みが複数のアクセント句にまたがる場合は、それらを一つのアクセント句につなげる。
+pub fn apply_readings(
+    accent_phrases: &mut Vec<AccentPhraseModel>,
+    readings: &[Reading],
+) -> Result<(), String> {
+    // 前の読みより後ろから探す。全アクセント句を通したモーラの番号
+    let mut search_from = 0;
+    for reading in readings {
+        let keys = kana2moras(&reading.kana)?
+            .iter()
+            .map(mora_key)
+            .collect::<Vec<_>>();
+        let positions = accent_phrases
+            .iter()
+            .enumerate()
+            .flat_map(|(p, accent_phrase)| {
+                accent_phrase
+                    .moras
+                    .iter()
+                    .enumerate()
+                    .map(move |(m, mora)| (p, m, mora_key(mora)))
+            })
+            .collect::<Vec<_>>();
+        let found = (search_from..(positions.len() + 1).saturating_sub(keys.len())).find(|&i| {
+            positions[i..i + keys.len()]
+                .iter()
+                .zip(keys.iter())
+                .all(|((_, _, key), expected)| key == expected)
+        });
+        let start = match found {
+            Some(start) => start,
+            None if reading.accent.is_some() => {
+                return Err(format!(
+                    "reading `{}` of `{}` was not found in the analyzed text",
+                    reading.kana, reading.base
+                ))
+            }
+            None => continue,
+        };
+        search_from = start + keys.len();
+
+        let accent = match reading.accent {
+            Some(accent) => accent,
+            None => continue,
+        };
+        let (first_phrase, first_mora, _) = positions[start];
+        let (last_phrase, _, _) = positions[start + keys.len() - 1];
+        for _ in first_phrase..last_phrase {
+            let latter = accent_phrases.remove(first_phrase + 1);
+            accent_phrases[first_phrase] = accent_phrases[first_phrase].merge(&latter);
+        }
+        // つなげてもモーラの並びは変わらないので、search_from はそのまま使える
+        accent_phrases[first_phrase].accent = first_mora as u32 + accent;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod inline_markup_tests {
+    use super::{apply_readings, parse_markup, split_chunks, MarkupToken, Reading};
+    use crate::direct_input::accent_phrases_from_kana;
+
+    #[test]
+    fn test_parse_markup() {
+        let tokens =
+            parse_markup("{東京|ト'ーきょー}へ/行く[pause:500ms]{漢字|かんじ}\\/").unwrap();
+        assert_eq!(
+            tokens,
+            [
+                MarkupToken::Reading(Reading {
+                    base: "東京".to_string(),
+                    kana: "トーキョー".to_string(),
+                    accent: Some(1),
+                }),
+                MarkupToken::Text("へ".to_string()),
+                MarkupToken::PhraseBreak,
+                MarkupToken::Text("行く".to_string()),
+                MarkupToken::Pause(0.5),
+                MarkupToken::Reading(Reading {
+                    base: "漢字".to_string(),
+                    kana: "カンジ".to_string(),
+                    accent: None,
+                }),
+                MarkupToken::Text("/".to_string()),
+            ]
+        );
+        let chunks = split_chunks(tokens);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].text, "トーキョーへ");
+        assert_eq!(chunks[1].pause_after, Some(0.5));
+        assert_eq!(chunks[2].text, "カンジ/");
+
+        assert!(parse_markup("{東京}").is_err());
+        assert!(parse_markup("[wait:1s]").is_err());
+        assert!(parse_markup("{東京|ト'ー'キョー}").is_err());
+    }
+
+    #[test]
+    fn test_apply_readings() {
+        // 日本語処理部が「トー」と「キョーエ」に分けた場合
+        let mut accent_phrases = accent_phrases_from_kana("トー/キョーエ/イク").unwrap();
+        let readings = [Reading {
+            base: "東京".to_string(),
+            kana: "トーキョー".to_string(),
+            accent: Some(1),
+        }];
+        apply_readings(&mut accent_phrases, &readings).unwrap();
+        assert_eq!(accent_phrases.len(), 2);
+        assert_eq!(accent_phrases[0].moras.len(), 5);
+        assert_eq!(accent_phrases[0].accent, 1);
+
+        let readings = [Reading {
+            base: "行く".to_string(),
+            kana: "イク".to_string(),
+            accent: Some(2),
+        }];
+        apply_readings(&mut accent_phrases, &readings).unwrap();
+        assert_eq!(accent_phrases[1].accent, 2);
+
+        let readings = [Reading {
+            base: "大阪".to_string(),
+            kana: "オーサカ".to_string(),
+            accent: Some(1),
+        }];
+        assert!(apply_readings(&mut accent_phrases, &readings).is_err());
+    }
+}
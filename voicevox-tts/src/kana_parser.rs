@@ -0,0 +1,277 @@
+//! VOICEVOX ENGINE の AquesTalk 風記法の読み込み
+//!
+//! - `'`: 直前のモーラをアクセント核とする。各アクセント句に一つ必要
+//! - `_`: 直後のモーラを無声化する
+//! - `/`: アクセント句の区切り
+//! - `、`: ポーズを伴うアクセント句の区切り
+//! - `？`: アクセント句の末尾に付けると疑問文の抑揚になる
+
+use std::fmt;
+
+use crate::{
+    frontend::make_pause_mora,
+    model::{AccentPhraseModel, MoraModel},
+    mora_list::text2mora_map,
+};
+
+const ACCENT_SYMBOL: char = '\'';
+const UNVOICE_SYMBOL: char = '_';
+const NOPAUSE_DELIMITER: char = '/';
+const PAUSE_DELIMITER: char = '、';
+const WIDE_INTERROGATION_MARK: char = '？';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KanaParseErrorKind {
+    /// モーラとして解釈できない文字列
+    UnknownText(String),
+    /// アクセント句の先頭にアクセント記号がある
+    AccentTop,
+    /// 一つのアクセント句にアクセント記号が二つ以上ある
+    AccentTwice,
+    /// アクセント記号の無いアクセント句がある
+    AccentNotFound,
+    /// 空のアクセント句がある
+    EmptyPhrase,
+    /// `？` がアクセント句の末尾以外にある
+    InterrogationMarkNotAtEnd,
+}
+
+/// 記法の誤り。`position` は入力テキスト中の位置（文字単位）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KanaParseError {
+    pub kind: KanaParseErrorKind,
+    pub position: usize,
+}
+
+impl fmt::Display for KanaParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            KanaParseErrorKind::UnknownText(text) => {
+                write!(f, "unknown text `{}` at {}", text, self.position)
+            }
+            KanaParseErrorKind::AccentTop => {
+                write!(
+                    f,
+                    "accent must not be at the top of a phrase at {}",
+                    self.position
+                )
+            }
+            KanaParseErrorKind::AccentTwice => {
+                write!(f, "accent is specified twice at {}", self.position)
+            }
+            KanaParseErrorKind::AccentNotFound => {
+                write!(f, "accent is not found in the phrase at {}", self.position)
+            }
+            KanaParseErrorKind::EmptyPhrase => {
+                write!(f, "empty accent phrase at {}", self.position)
+            }
+            KanaParseErrorKind::InterrogationMarkNotAtEnd => write!(
+                f,
+                "interrogation mark must be at the end of a phrase at {}",
+                self.position
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KanaParseError {}
+
+fn error(kind: KanaParseErrorKind, position: usize) -> KanaParseError {
+    KanaParseError { kind, position }
+}
+
+/// 一つのアクセント句を読み込む。`offset` はアクセント句の入力テキスト中の位置
+fn parse_accent_phrase(
+    phrase: &[char],
+    offset: usize,
+) -> Result<AccentPhraseModel, KanaParseError> {
+    let map = text2mora_map();
+    let mut moras = Vec::new();
+    let mut accent = None;
+
+    let mut i = 0;
+    while i < phrase.len() {
+        if phrase[i] == ACCENT_SYMBOL {
+            if moras.is_empty() {
+                return Err(error(KanaParseErrorKind::AccentTop, offset + i));
+            }
+            if accent.is_some() {
+                return Err(error(KanaParseErrorKind::AccentTwice, offset + i));
+            }
+            accent = Some(moras.len() as u32);
+            i += 1;
+            continue;
+        }
+
+        let is_unvoiced = phrase[i] == UNVOICE_SYMBOL;
+        let start = if is_unvoiced { i + 1 } else { i };
+        let found = [2, 1].iter().find_map(|&len| {
+            let text = phrase.get(start..start + len)?.iter().collect::<String>();
+            map.get(text.as_str())
+                .map(|&(consonant, vowel)| (len, text, consonant, vowel))
+        });
+        let (len, text, consonant, vowel) = match found {
+            Some(found) => found,
+            None => {
+                let text = phrase[i..(start + 1).min(phrase.len())].iter().collect();
+                return Err(error(KanaParseErrorKind::UnknownText(text), offset + i));
+            }
+        };
+        // 無声化できるのは母音 a i u e o のみ
+        let vowel = if is_unvoiced {
+            if !"aiueo".contains(vowel) {
+                let text = format!("{}{}", UNVOICE_SYMBOL, text);
+                return Err(error(KanaParseErrorKind::UnknownText(text), offset + i));
+            }
+            vowel.to_uppercase()
+        } else {
+            vowel.to_string()
+        };
+
+        moras.push(MoraModel {
+            text,
+            consonant: if consonant.is_empty() {
+                None
+            } else {
+                Some(consonant.to_string())
+            },
+            consonant_length: if consonant.is_empty() {
+                None
+            } else {
+                Some(0.0)
+            },
+            vowel,
+            vowel_length: 0.0,
+            pitch: 0.0,
+        });
+        i = start + len;
+    }
+
+    match accent {
+        Some(accent) => Ok(AccentPhraseModel {
+            moras,
+            accent,
+            pause_mora: None,
+            is_interrogative: false,
+        }),
+        None => Err(error(KanaParseErrorKind::AccentNotFound, offset)),
+    }
+}
+
+/// AquesTalk 風記法のテキストをアクセント句に変換する
+///
+/// 例: `コンニチワ'、キョ'ウワ/イ'イ/テ'ンキ_デスネ`
+pub fn parse_kana(text: &str) -> Result<Vec<AccentPhraseModel>, KanaParseError> {
+    let chars = text.chars().collect::<Vec<_>>();
+    if chars.is_empty() {
+        return Err(error(KanaParseErrorKind::EmptyPhrase, 0));
+    }
+
+    let mut accent_phrases = Vec::new();
+    let mut phrase_base = 0;
+    for i in 0..=chars.len() {
+        let delimiter = chars.get(i).copied();
+        if !matches!(
+            delimiter,
+            None | Some(PAUSE_DELIMITER) | Some(NOPAUSE_DELIMITER)
+        ) {
+            continue;
+        }
+
+        let mut phrase = &chars[phrase_base..i];
+        if phrase.is_empty() {
+            return Err(error(KanaParseErrorKind::EmptyPhrase, phrase_base));
+        }
+        let is_interrogative = phrase.last() == Some(&WIDE_INTERROGATION_MARK);
+        if is_interrogative {
+            phrase = &phrase[..phrase.len() - 1];
+        }
+        if let Some(position) = phrase.iter().position(|c| *c == WIDE_INTERROGATION_MARK) {
+            return Err(error(
+                KanaParseErrorKind::InterrogationMarkNotAtEnd,
+                phrase_base + position,
+            ));
+        }
+
+        let mut accent_phrase = parse_accent_phrase(phrase, phrase_base)?;
+        accent_phrase.is_interrogative = is_interrogative;
+        if delimiter == Some(PAUSE_DELIMITER) {
+            accent_phrase.pause_mora = Some(make_pause_mora());
+        }
+        accent_phrases.push(accent_phrase);
+        phrase_base = i + 1;
+    }
+    Ok(accent_phrases)
+}
+
+/// アクセント句を AquesTalk 風記法のテキストに変換する
+pub fn create_kana(accent_phrases: &[AccentPhraseModel]) -> String {
+    let mut text = String::new();
+    for (i, accent_phrase) in accent_phrases.iter().enumerate() {
+        for (j, mora) in accent_phrase.moras.iter().enumerate() {
+            if ["A", "I", "U", "E", "O"].contains(&mora.vowel.as_str()) {
+                text.push(UNVOICE_SYMBOL);
+            }
+            text.push_str(&mora.text);
+            if j + 1 == accent_phrase.accent as usize {
+                text.push(ACCENT_SYMBOL);
+            }
+        }
+        if accent_phrase.is_interrogative {
+            text.push(WIDE_INTERROGATION_MARK);
+        }
+        if i + 1 < accent_phrases.len() {
+            text.push(if accent_phrase.pause_mora.is_some() {
+                PAUSE_DELIMITER
+            } else {
+                NOPAUSE_DELIMITER
+            });
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod kana_parser_tests {
+    use super::{create_kana, parse_kana, KanaParseErrorKind};
+
+    #[test]
+    fn test_parse_kana() {
+        let accent_phrases = parse_kana("コンニチワ'、キョ'ウ_ワ/テ'ンキ？").unwrap();
+        assert_eq!(accent_phrases.len(), 3);
+        assert_eq!(accent_phrases[0].accent, 5);
+        assert!(accent_phrases[0].pause_mora.is_some());
+        assert_eq!(accent_phrases[1].moras[0].text, "キョ");
+        assert_eq!(accent_phrases[1].moras[2].vowel, "A");
+        assert!(accent_phrases[1].pause_mora.is_none());
+        assert!(accent_phrases[2].is_interrogative);
+    }
+
+    #[test]
+    fn test_create_kana_round_trip() {
+        let kana = "コンニチワ'、キョ'ウ_ワ/テ'ンキ？";
+        assert_eq!(create_kana(&parse_kana(kana).unwrap()), kana);
+    }
+
+    #[test]
+    fn test_parse_kana_error() {
+        let kinds = ["'ア", "ア'イ'", "アイ", "ア'//イ'", "ア？イ'", "ア'ｘ"]
+            .iter()
+            .map(|text| {
+                let err = parse_kana(text).unwrap_err();
+                (err.kind, err.position)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                (KanaParseErrorKind::AccentTop, 0),
+                (KanaParseErrorKind::AccentTwice, 3),
+                (KanaParseErrorKind::AccentNotFound, 0),
+                (KanaParseErrorKind::EmptyPhrase, 3),
+                (KanaParseErrorKind::InterrogationMarkNotAtEnd, 1),
+                (KanaParseErrorKind::UnknownText("ｘ".to_string()), 2),
+            ]
+        );
+    }
+}
@@ -1,13 +1,48 @@
+pub mod accent_phrase_edit;
 pub mod acoustic_feature_extractor;
+pub mod aozora;
+#[cfg(feature = "async")]
+pub mod async_engine;
+pub mod audio;
+pub mod audio_query;
+pub mod dialogue;
+pub mod direct_input;
+pub mod document;
+pub mod encoder;
+pub mod frontend;
 pub mod full_context_label;
+pub mod inline_markup;
+pub mod kana_parser;
+pub mod loudness;
+pub mod metas;
 pub mod model;
 pub mod mora_list;
+pub mod postprocess;
+pub mod preset;
+pub mod pronunciation_report;
+pub mod resampler;
+pub mod rewrite_rule;
+pub mod ssml;
+pub mod stream;
+pub mod tts;
 
 use std::path::Path;
 
-use model::AudioQueryModel;
+use accent_phrase_edit::AccentPhraseEdit;
+use aozora::{find_chapters, parse_aozora, AozoraResult};
+use dialogue::{split_speaker, DialogueResult, DialogueScript};
+use document::{split_sentences, DocumentOptions, DocumentProgress, DocumentResult};
+use frontend::{Frontend, Word};
+use metas::{find_style_id, parse_metas, SpeakerMeta};
+use model::{AccentPhraseModel, AudioQueryModel};
 pub use openjtalk::OpenJTalk;
+use preset::PresetManager;
+use pronunciation_report::PronunciationReport;
+use rewrite_rule::RewriteRules;
+use ssml::{parse_ssml, Prosody, SsmlContent, SsmlItem, SsmlResult};
+use stream::SynthesisStream;
 use synthesis_engine::SynthesisEngine;
+use tts::{TtsOptions, TtsResult};
 pub use voicevox_core::VVCore;
 
 pub mod synthesis_engine;
@@ -15,6 +50,7 @@ pub mod synthesis_engine;
 pub struct VVTTSEngine {
     openjtalk: OpenJTalk,
     synthesis_engine: SynthesisEngine,
+    presets: PresetManager,
 }
 
 impl VVTTSEngine {
@@ -23,6 +59,7 @@ impl VVTTSEngine {
         VVTTSEngine {
             openjtalk,
             synthesis_engine,
+            presets: PresetManager::new(),
         }
     }
 
@@ -30,29 +67,565 @@ impl VVTTSEngine {
         self.openjtalk.load(openjtalk_dict_path)
     }
 
-    pub fn tts<T: AsRef<str>>(&self, text: T, speaker_id: i64) -> Result<Vec<u8>, String> {
+    /// テキストの解析に使う日本語処理部を OpenJTalk から差し替える
+    ///
+    /// 書き換え規則を設定している場合、差し替えた日本語処理部での合成はエラーになる。
+    pub fn set_frontend(&mut self, frontend: Box<dyn Frontend>) {
+        self.synthesis_engine.set_frontend(frontend);
+    }
+
+    pub fn set_rewrite_rules(&mut self, rewrite_rules: RewriteRules) {
+        self.synthesis_engine.set_rewrite_rules(rewrite_rules);
+    }
+
+    pub fn set_presets(&mut self, presets: PresetManager) {
+        self.presets = presets;
+    }
+
+    pub fn presets(&self) -> &PresetManager {
+        &self.presets
+    }
+
+    pub fn presets_mut(&mut self) -> &mut PresetManager {
+        &mut self.presets
+    }
+
+    /// 未知語や数字・記号の展開など、読み間違いの可能性がある箇所を報告する
+    pub fn pronunciation_report<T: AsRef<str>>(
+        &self,
+        text: T,
+    ) -> Result<PronunciationReport, String> {
+        pronunciation_report::analyze(self.openjtalk, text.as_ref())
+    }
+
+    /// テキストを日本語処理部で単語に分ける
+    pub fn words<T: AsRef<str>>(&self, text: T) -> Result<Vec<Word>, String> {
+        self.synthesis_engine.words(text.as_ref())
+    }
+
+    /// テキストから VOICEVOX ENGINE の AudioQuery に相当するクエリを作る
+    ///
+    /// `kana` には OpenJTalk が選んだ読みとアクセントが AquesTalk 風記法で入る。
+    pub fn audio_query<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+    ) -> Result<AudioQueryModel, String> {
         let accent_phrases = self
             .synthesis_engine
             .create_accent_phrases(text.as_ref().to_string(), speaker_id)?;
-        let audio_query = AudioQueryModel {
-            accent_phrases,
-            speed_scale: 1.0,
-            pitch_scale: 0.0,
-            intonation_scale: 1.0,
-            volume_scale: 1.0,
-            pre_phoneme_length: 0.1,
-            post_phoneme_length: 0.1,
-            output_sampling_rate: synthesis_engine::DEFAULT_SAMPLING_RATE,
-            output_stereo: false,
-            kana: "".to_string(),
+        audio_query_from_accent_phrases(accent_phrases)
+    }
+
+    /// [`audio_query`](Self::audio_query) と同じクエリを作り、同じ解析で分けた単語と一緒に返す
+    pub fn audio_query_with_words<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+    ) -> Result<(AudioQueryModel, Vec<Word>), String> {
+        let (accent_phrases, words) = self
+            .synthesis_engine
+            .create_accent_phrases_with_words(text.as_ref(), speaker_id)?;
+        Ok((audio_query_from_accent_phrases(accent_phrases)?, words))
+    }
+
+    /// 読みとアクセントを書き込んだテキストからクエリを作る
+    ///
+    /// 記法は [`inline_markup`] を参照。
+    pub fn audio_query_from_markup<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+    ) -> Result<AudioQueryModel, String> {
+        let chunks = inline_markup::split_chunks(inline_markup::parse_markup(text.as_ref())?);
+        let mut accent_phrases = Vec::new();
+        // 音素長を予測したあとに長さを設定するポーズ（アクセント句の番号と秒）
+        let mut pauses = Vec::new();
+        for chunk in chunks {
+            let mut chunk_phrases = self.synthesis_engine.analyze_text(&chunk.text)?;
+            inline_markup::apply_readings(&mut chunk_phrases, &chunk.readings)?;
+            accent_phrases.append(&mut chunk_phrases);
+            if let Some(length) = chunk.pause_after {
+                if let Some(last) = accent_phrases.last_mut() {
+                    if last.pause_mora.is_none() {
+                        last.pause_mora = Some(frontend::make_pause_mora());
+                    }
+                    pauses.push((accent_phrases.len() - 1, length));
+                }
+            }
+        }
+        let mut accent_phrases = if accent_phrases.is_empty() {
+            accent_phrases
+        } else {
+            self.synthesis_engine
+                .replace_mora_data(accent_phrases, speaker_id)?
         };
+        for (index, length) in pauses {
+            if let Some(ref mut pause_mora) = accent_phrases[index].pause_mora {
+                pause_mora.vowel_length = length;
+            }
+        }
+        audio_query_from_accent_phrases(accent_phrases)
+    }
+
+    /// 読みとアクセントを書き込んだテキストから音声を合成する
+    ///
+    /// 読みを書き込んだ部分は単語に分けられないので、単語の時刻は求めない。
+    pub fn tts_from_markup<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &TtsOptions,
+    ) -> Result<TtsResult, String> {
+        let mut audio_query = self.audio_query_from_markup(text, speaker_id)?;
+        options.apply(&mut audio_query);
+        let mut result = self.synthesis(
+            audio_query,
+            speaker_id,
+            options.enable_interrogative_upspeak,
+        )?;
+        options.finish(&mut result.audio);
+        Ok(result)
+    }
 
+    /// [`audio_query`](Self::audio_query) で得たアクセント句を編集する
+    ///
+    /// 編集したアクセント句以外の音素長と音高は、手で調整した値がそのまま残る。
+    pub fn edit_accent_phrases(
+        &self,
+        accent_phrases: Vec<AccentPhraseModel>,
+        edit: &AccentPhraseEdit,
+        speaker_id: i64,
+    ) -> Result<Vec<AccentPhraseModel>, String> {
+        self.synthesis_engine
+            .edit_accent_phrases(accent_phrases, edit, speaker_id)
+    }
+
+    pub fn tts<T: AsRef<str>>(&self, text: T, speaker_id: i64) -> Result<Vec<u8>, String> {
+        let audio_query = self.audio_query(text, speaker_id)?;
         self.synthesis_engine
             .synthesis_wave_format(audio_query, speaker_id, true)
     }
 
+    /// クエリから音声を合成し、音声のサンプルとタイミングを返す
+    pub fn synthesis(
+        &self,
+        audio_query: AudioQueryModel,
+        speaker_id: i64,
+        enable_interrogative_upspeak: bool,
+    ) -> Result<TtsResult, String> {
+        let (wave, timings) = self.synthesis_engine.synthesis_with_timings(
+            audio_query.clone(),
+            speaker_id,
+            enable_interrogative_upspeak,
+        )?;
+        TtsResult::new(wave, audio_query, timings)
+    }
+
+    /// クエリから息継ぎごとに音声を合成する
+    ///
+    /// 最初の区間の音声ができた時点で再生を始められる。
+    pub fn synthesis_stream(
+        &self,
+        audio_query: AudioQueryModel,
+        speaker_id: i64,
+        enable_interrogative_upspeak: bool,
+    ) -> Result<SynthesisStream<'_>, String> {
+        self.synthesis_engine.synthesis_stream(
+            audio_query,
+            speaker_id,
+            enable_interrogative_upspeak,
+        )
+    }
+
+    /// オプションを指定してテキストから息継ぎごとに音声を合成する
+    ///
+    /// 音声全体にかける `loudness` と `post_process` は使えないので、指定した場合はエラーにする。
+    pub fn tts_stream<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &TtsOptions,
+    ) -> Result<SynthesisStream<'_>, String> {
+        if options.loudness.is_some() {
+            return Err(
+                "loudness normalization can't be used with streaming synthesis".to_string(),
+            );
+        }
+        if !options.post_process.is_empty() {
+            return Err("post-processing can't be used with streaming synthesis".to_string());
+        }
+        let mut audio_query = self.audio_query(text, speaker_id)?;
+        options.apply(&mut audio_query);
+        self.synthesis_stream(
+            audio_query,
+            speaker_id,
+            options.enable_interrogative_upspeak,
+        )
+    }
+
+    /// オプションを指定してテキストから音声を合成する
+    pub fn tts_with_options<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &TtsOptions,
+    ) -> Result<TtsResult, String> {
+        self.tts_with_options_checked(text, speaker_id, options, || Ok(()))
+    }
+
+    /// テキストの解析と音声合成の間で `check` を呼び、エラーならそこで止める
+    pub(crate) fn tts_with_options_checked<T, F>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &TtsOptions,
+        check: F,
+    ) -> Result<TtsResult, String>
+    where
+        T: AsRef<str>,
+        F: Fn() -> Result<(), String>,
+    {
+        let (mut audio_query, words) = self.audio_query_with_words(text, speaker_id)?;
+        check()?;
+        options.apply(&mut audio_query);
+        let mut result = self.synthesis(
+            audio_query,
+            speaker_id,
+            options.enable_interrogative_upspeak,
+        )?;
+        result.audio.timings.set_words(&words)?;
+        options.finish(&mut result.audio);
+        Ok(result)
+    }
+
+    /// 複数の文や段落からなる文章を、文ごとに合成してつなげる
+    pub fn tts_document<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &DocumentOptions,
+    ) -> Result<DocumentResult, String> {
+        self.tts_document_with_progress(text, speaker_id, options, |_| {})
+    }
+
+    /// 文を一つ合成するたびに `progress` を呼ぶ
+    pub fn tts_document_with_progress<T, F>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &DocumentOptions,
+        mut progress: F,
+    ) -> Result<DocumentResult, String>
+    where
+        T: AsRef<str>,
+        F: FnMut(DocumentProgress),
+    {
+        let sentences = split_sentences(text.as_ref());
+        // 後処理とラウドネスの正規化は文ごとではなく文章全体にかける
+        let sentence_options = TtsOptions {
+            loudness: None,
+            post_process: Vec::new(),
+            ..options.tts.clone()
+        };
+        let mut result = DocumentResult::new(options);
+        for (index, sentence) in sentences.iter().enumerate() {
+            let tts_result =
+                self.tts_with_options(&sentence.text, speaker_id, &sentence_options)?;
+            let pause_length = if index + 1 == sentences.len() {
+                0.0
+            } else {
+                options.pause_length(sentence.boundary)
+            };
+            result.push(sentence, tts_result, pause_length);
+            progress(DocumentProgress {
+                index,
+                total: sentences.len(),
+                sentence: &sentence.text,
+            });
+        }
+        options.tts.finish(&mut result.audio);
+        Ok(result)
+    }
+
+    /// 青空文庫の書式のテキストを、ルビを読みとして文章として合成する
+    ///
+    /// 見出しは節の区切りとして前後に `section_pause` の無音を入れ、その始まる時刻を章の目印として返す。
+    pub fn tts_aozora<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &DocumentOptions,
+    ) -> Result<AozoraResult, String> {
+        let aozora = parse_aozora(text.as_ref());
+        let document = self.tts_document(&aozora.body, speaker_id, options)?;
+        let chapters = find_chapters(&aozora.headings, &document);
+        Ok(AozoraResult { document, chapters })
+    }
+
+    /// 音声合成モデルの話者とスタイルの一覧
+    pub fn metas(&self) -> Result<Vec<SpeakerMeta>, String> {
+        parse_metas(&self.synthesis_engine.metas())
+    }
+
+    /// 台本の各行を話者ごとに合成し、一つの音声にする
+    ///
+    /// 話者名は [`metas`](VVTTSEngine::metas) から引く。`options` は各行の合成パラメータの既定値で、
+    /// 出力のチャンネル数は台本のパンの指定で決まる。
+    pub fn render_dialogue(
+        &self,
+        script: &DialogueScript,
+        options: &TtsOptions,
+    ) -> Result<DialogueResult, String> {
+        script.validate()?;
+        let metas = self.metas()?;
+        let line_options = TtsOptions {
+            output_stereo: false,
+            ..options.clone()
+        };
+        let mut result = DialogueResult::new(script, options.output_sampling_rate);
+        for (i, line) in script.lines.iter().enumerate() {
+            let error = |e: String| format!("line {}: {}", i + 1, e);
+            let speaker_id =
+                find_style_id(&metas, &line.speaker, line.style.as_deref()).map_err(error)?;
+            let (mut audio_query, words) = self
+                .audio_query_with_words(&line.text, speaker_id)
+                .map_err(error)?;
+            line_options.apply(&mut audio_query);
+            if let Some(ref name) = line.preset {
+                self.presets
+                    .get(name)
+                    .ok_or_else(|| error(format!("preset `{}` is not found", name)))?
+                    .apply(&mut audio_query);
+            }
+            if let Some(speed_scale) = line.speed_scale {
+                audio_query.speed_scale = speed_scale;
+            }
+            if let Some(pitch_scale) = line.pitch_scale {
+                audio_query.pitch_scale = pitch_scale;
+            }
+            if let Some(intonation_scale) = line.intonation_scale {
+                audio_query.intonation_scale = intonation_scale;
+            }
+            if let Some(volume_scale) = line.volume_scale {
+                audio_query.volume_scale = volume_scale;
+            }
+            let mut line_result = self
+                .synthesis(
+                    audio_query,
+                    speaker_id,
+                    options.enable_interrogative_upspeak,
+                )
+                .map_err(error)?;
+            line_result.audio.timings.set_words(&words).map_err(error)?;
+            let pause_after = if i + 1 == script.lines.len() {
+                0.0
+            } else {
+                line.pause_after.unwrap_or(script.pause_after)
+            };
+            result.push(script, line, speaker_id, line_result, pause_after);
+        }
+        options.finish(&mut result.audio);
+        Ok(result)
+    }
+
+    /// SSML の一部に対応した入力から音声を合成する
+    ///
+    /// 話者と `<prosody>` などの指定が同じ部分は一つのクエリにまとめて合成する。
+    /// まとめた部分は元のテキストと対応しないため、単語の時刻は求めない。
+    /// `speaker_id` は `<voice>` の外側の話者で、`<voice name>` は
+    /// [`metas`](VVTTSEngine::metas) の話者名（`四国めたん（あまあま）` のようにスタイル名も付けられる）で指定する。
+    pub fn tts_ssml(
+        &self,
+        ssml: &str,
+        speaker_id: i64,
+        options: &TtsOptions,
+    ) -> Result<SsmlResult, String> {
+        let items = parse_ssml(ssml)?;
+        let mut metas = None;
+        let mut result = SsmlResult::new(options);
+        // 話者と合成パラメータが同じアクセント句をまとめて合成する
+        let mut unit: Option<(i64, Prosody, Vec<AccentPhraseModel>)> = None;
+        for item in items {
+            match item {
+                SsmlItem::Break(length) => {
+                    let last_phrase = unit
+                        .as_mut()
+                        .and_then(|(_, _, accent_phrases)| accent_phrases.last_mut());
+                    match last_phrase {
+                        Some(accent_phrase) => {
+                            let mut pause_mora = accent_phrase
+                                .pause_mora
+                                .take()
+                                .unwrap_or_else(frontend::make_pause_mora);
+                            pause_mora.vowel_length = length;
+                            accent_phrase.pause_mora = Some(pause_mora);
+                        }
+                        None => result.audio.push_silence(length),
+                    }
+                }
+                SsmlItem::Speech {
+                    content,
+                    voice,
+                    prosody,
+                } => {
+                    let item_speaker_id = match voice {
+                        Some(voice) => {
+                            if metas.is_none() {
+                                metas = Some(self.metas()?);
+                            }
+                            let (name, style) = split_speaker(&voice);
+                            find_style_id(metas.as_ref().unwrap(), &name, style.as_deref())?
+                        }
+                        None => speaker_id,
+                    };
+                    let mut accent_phrases = match content {
+                        SsmlContent::Text(text) => self
+                            .synthesis_engine
+                            .create_accent_phrases(text, item_speaker_id)?,
+                        SsmlContent::Kana(kana) => self
+                            .synthesis_engine
+                            .create_accent_phrases_from_kana(&kana, item_speaker_id)?,
+                    };
+                    match unit {
+                        Some((unit_speaker_id, unit_prosody, ref mut unit_phrases))
+                            if unit_speaker_id == item_speaker_id && unit_prosody == prosody =>
+                        {
+                            unit_phrases.append(&mut accent_phrases);
+                        }
+                        _ => {
+                            if let Some(unit) = unit.take() {
+                                self.synthesis_ssml_unit(unit, options, &mut result)?;
+                            }
+                            unit = Some((item_speaker_id, prosody, accent_phrases));
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(unit) = unit {
+            self.synthesis_ssml_unit(unit, options, &mut result)?;
+        }
+        options.finish(&mut result.audio);
+        Ok(result)
+    }
+
+    fn synthesis_ssml_unit(
+        &self,
+        (speaker_id, prosody, accent_phrases): (i64, Prosody, Vec<AccentPhraseModel>),
+        options: &TtsOptions,
+        result: &mut SsmlResult,
+    ) -> Result<(), String> {
+        if accent_phrases.is_empty() {
+            return Ok(());
+        }
+        let mut audio_query = audio_query_from_accent_phrases(accent_phrases)?;
+        options.apply(&mut audio_query);
+        prosody.apply(&mut audio_query);
+        let tts_result = self.synthesis(
+            audio_query,
+            speaker_id,
+            options.enable_interrogative_upspeak,
+        )?;
+        result.push(speaker_id, tts_result);
+        Ok(())
+    }
+
+    /// オプションの `output_format` で指定した形式の音声を返す
+    pub fn tts_encoded<T: AsRef<str>>(
+        &self,
+        text: T,
+        speaker_id: i64,
+        options: &TtsOptions,
+    ) -> Result<Vec<u8>, String> {
+        self.tts_with_options(text, speaker_id, options)?
+            .audio
+            .encode(options.output_format)
+    }
+
+    /// 名前で指定したプリセットの話者と合成パラメータで音声を合成する
+    pub fn tts_with_preset<T: AsRef<str>>(
+        &self,
+        text: T,
+        preset_name: &str,
+    ) -> Result<Vec<u8>, String> {
+        let preset = self
+            .presets
+            .get(preset_name)
+            .ok_or_else(|| format!("preset `{}` is not found", preset_name))?;
+        let mut audio_query = self.audio_query(text, preset.speaker_id)?;
+        preset.apply(&mut audio_query);
+        self.synthesis_engine
+            .synthesis_wave_format(audio_query, preset.speaker_id, true)
+    }
+
+    /// 形態素解析を通さずに、読みから音声を合成する
+    ///
+    /// 記法は [`direct_input::accent_phrases_from_kana`] を参照。
+    pub fn tts_from_kana<T: AsRef<str>>(
+        &self,
+        kana: T,
+        speaker_id: i64,
+    ) -> Result<Vec<u8>, String> {
+        let accent_phrases = direct_input::accent_phrases_from_kana(kana.as_ref())?;
+        let accent_phrases = self
+            .synthesis_engine
+            .replace_mora_data(accent_phrases, speaker_id)?;
+        self.synthesis_accent_phrases(accent_phrases, speaker_id)
+    }
+
+    /// 形態素解析を通さずに、音素列から音声を合成する
+    ///
+    /// 記法は [`direct_input::accent_phrases_from_phonemes`] を参照。
+    pub fn tts_from_phonemes<T: AsRef<str>>(
+        &self,
+        phonemes: T,
+        speaker_id: i64,
+    ) -> Result<Vec<u8>, String> {
+        let accent_phrases = direct_input::accent_phrases_from_phonemes(phonemes.as_ref())?;
+        let accent_phrases = self
+            .synthesis_engine
+            .replace_mora_data(accent_phrases, speaker_id)?;
+        self.synthesis_accent_phrases(accent_phrases, speaker_id)
+    }
+
+    /// AquesTalk 風記法のテキストから音声を合成する
+    ///
+    /// 記法は [`kana_parser`] を参照。
+    pub fn tts_from_aquestalk_kana<T: AsRef<str>>(
+        &self,
+        kana: T,
+        speaker_id: i64,
+    ) -> Result<Vec<u8>, String> {
+        let accent_phrases = self
+            .synthesis_engine
+            .create_accent_phrases_from_kana(kana.as_ref(), speaker_id)?;
+        self.synthesis_accent_phrases(accent_phrases, speaker_id)
+    }
+
+    fn synthesis_accent_phrases(
+        &self,
+        accent_phrases: Vec<AccentPhraseModel>,
+        speaker_id: i64,
+    ) -> Result<Vec<u8>, String> {
+        self.synthesis_engine.synthesis_wave_format(
+            audio_query_from_accent_phrases(accent_phrases)?,
+            speaker_id,
+            true,
+        )
+    }
+
     pub fn finalize(&self) {
         self.openjtalk.delete();
         self.synthesis_engine.finalize();
     }
 }
+
+fn audio_query_from_accent_phrases(
+    accent_phrases: Vec<AccentPhraseModel>,
+) -> Result<AudioQueryModel, String> {
+    let kana = kana_parser::create_kana(&accent_phrases);
+    AudioQueryModel::builder(accent_phrases)
+        .kana(kana)
+        .build_generated()
+}
@@ -0,0 +1,351 @@
+//! ラウドネスの正規化とトゥルーピークリミッター
+//!
+//! ラウドネスは ITU-R BS.1770 の K 特性とゲートで測り、LUFS で表す。
+//! トゥルーピークは 4 倍にオーバーサンプリングして求める。
+
+use std::{collections::VecDeque, f64::consts::PI};
+
+/// ラウドネスの正規化のオプション
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessOptions {
+    /// 目標のラウドネス（LUFS）
+    pub target_loudness: f32,
+    /// トゥルーピークの上限（dBTP）
+    pub true_peak_limit: f32,
+    /// リミッターが先読みする長さ（秒）
+    pub lookahead: f32,
+    /// リミッターのゲインが戻る時定数（秒）
+    pub release: f32,
+}
+
+impl Default for LoudnessOptions {
+    fn default() -> Self {
+        LoudnessOptions::broadcast()
+    }
+}
+
+impl LoudnessOptions {
+    /// 放送向け（EBU R128）の -23 LUFS、-1 dBTP
+    pub fn broadcast() -> LoudnessOptions {
+        LoudnessOptions {
+            target_loudness: -23.0,
+            true_peak_limit: -1.0,
+            lookahead: 0.005,
+            release: 0.05,
+        }
+    }
+
+    /// ポッドキャスト向けの -16 LUFS、-1 dBTP
+    pub fn podcast() -> LoudnessOptions {
+        LoudnessOptions {
+            target_loudness: -16.0,
+            ..LoudnessOptions::broadcast()
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[1] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[2] * y;
+        y
+    }
+}
+
+/// K 特性のフィルター（高域のシェルビングと低域のハイパス）の係数
+fn k_weighting(sampling_rate: u32) -> [Biquad; 2] {
+    let rate = sampling_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// 積分ラウドネス（LUFS）を測る。無音の場合は `None`
+///
+/// 400 ms のブロックを 100 ms ずつずらして測り、-70 LUFS の絶対ゲートと -10 LU の相対ゲートをかける。
+/// 400 ms より短い音声は全体を一つのブロックとして扱う。
+pub fn integrated_loudness(samples: &[f32], sampling_rate: u32, channels: u16) -> Option<f32> {
+    let channels = channels as usize;
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return None;
+    }
+
+    // K 特性をかけた信号の二乗をチャンネルについて足したもの
+    let mut filters = vec![k_weighting(sampling_rate); channels];
+    let powers = samples
+        .chunks_exact(channels)
+        .map(|frame| {
+            frame
+                .iter()
+                .zip(filters.iter_mut())
+                .map(|(sample, filter)| {
+                    let y = filter
+                        .iter_mut()
+                        .fold(*sample as f64, |x, biquad| biquad.process(x));
+                    y * y
+                })
+                .sum::<f64>()
+        })
+        .collect::<Vec<_>>();
+    let mut prefix = vec![0.0];
+    for power in powers.iter() {
+        prefix.push(prefix.last().unwrap() + power);
+    }
+
+    let block = ((sampling_rate as f64 * 0.4).round() as usize).min(frames);
+    let step = ((sampling_rate as f64 * 0.1).round() as usize).max(1);
+    let blocks = (0..=frames - block)
+        .step_by(step)
+        .map(|start| (prefix[start + block] - prefix[start]) / block as f64)
+        .collect::<Vec<_>>();
+
+    let loudness = |power: f64| -0.691 + 10.0 * power.log10();
+    let gated_mean = |threshold: f64| {
+        let gated = blocks
+            .iter()
+            .filter(|power| loudness(**power) > threshold)
+            .collect::<Vec<_>>();
+        if gated.is_empty() {
+            None
+        } else {
+            Some(gated.iter().copied().sum::<f64>() / gated.len() as f64)
+        }
+    };
+    let relative_threshold = loudness(gated_mean(-70.0)?) - 10.0;
+    let power = gated_mean(relative_threshold.max(-70.0))?;
+    Some(loudness(power) as f32)
+}
+
+const OVERSAMPLING: usize = 4;
+const INTERPOLATION_TAPS: usize = 12;
+
+/// サンプルの間を補間するフィルター。位相ごとに `INTERPOLATION_TAPS` 個の係数を持つ
+fn interpolation_table() -> Vec<[f64; INTERPOLATION_TAPS]> {
+    let half = (INTERPOLATION_TAPS / 2) as f64;
+    (1..OVERSAMPLING)
+        .map(|phase| {
+            let offset = phase as f64 / OVERSAMPLING as f64;
+            let mut taps = [0.0; INTERPOLATION_TAPS];
+            for (k, tap) in taps.iter_mut().enumerate() {
+                // サンプル n + k - half + 1 から n + offset までの距離
+                let distance = offset - (k as f64 - half + 1.0);
+                let sinc = if distance == 0.0 {
+                    1.0
+                } else {
+                    (PI * distance).sin() / (PI * distance)
+                };
+                let window = 0.5 * (1.0 + (PI * distance / half).cos());
+                *tap = sinc * window;
+            }
+            taps
+        })
+        .collect()
+}
+
+/// フレームごとの、そのフレームから次のフレームまでのトゥルーピーク（全チャンネルの最大）
+fn true_peaks(samples: &[f32], channels: u16) -> Vec<f32> {
+    let channels = channels as usize;
+    let frames = samples.len() / channels;
+    let table = interpolation_table();
+    let half = INTERPOLATION_TAPS / 2;
+    let mut peaks = vec![0.0f32; frames];
+    for channel in 0..channels {
+        let sample = |i: isize| {
+            if i < 0 || i as usize >= frames {
+                0.0
+            } else {
+                samples[i as usize * channels + channel] as f64
+            }
+        };
+        for (n, peak) in peaks.iter_mut().enumerate() {
+            let mut max = sample(n as isize).abs();
+            for taps in table.iter() {
+                let value = taps
+                    .iter()
+                    .enumerate()
+                    .map(|(k, tap)| tap * sample(n as isize + k as isize - half as isize + 1))
+                    .sum::<f64>();
+                max = max.max(value.abs());
+            }
+            *peak = peak.max(max as f32);
+        }
+    }
+    peaks
+}
+
+/// トゥルーピーク（dBTP）
+pub fn true_peak(samples: &[f32], channels: u16) -> f32 {
+    let peak = true_peaks(samples, channels)
+        .into_iter()
+        .fold(0.0f32, f32::max);
+    20.0 * peak.log10()
+}
+
+/// トゥルーピークが `limit`（dBTP）を超えないように音量を下げる
+///
+/// ピークの `lookahead` 秒前からゲインを滑らかに下げ、`release` 秒の時定数で戻す。
+pub fn limit_true_peak(
+    samples: &mut [f32],
+    sampling_rate: u32,
+    channels: u16,
+    limit: f32,
+    lookahead: f32,
+    release: f32,
+) {
+    let ceiling = 10f32.powf(limit / 20.0);
+    let gains = true_peaks(samples, channels)
+        .into_iter()
+        .map(|peak| if peak > ceiling { ceiling / peak } else { 1.0 })
+        .collect::<Vec<_>>();
+    if gains.iter().all(|gain| *gain == 1.0) {
+        return;
+    }
+    let frames = gains.len();
+    let length = ((lookahead * sampling_rate as f32).round() as usize).max(2);
+
+    // 前後 length - 1 フレームの最小値
+    let mut minimums = vec![1.0f32; frames];
+    let mut window: VecDeque<usize> = VecDeque::new();
+    for i in 0..frames + length - 1 {
+        if i < frames {
+            while window.back().is_some_and(|&j| gains[j] >= gains[i]) {
+                window.pop_back();
+            }
+            window.push_back(i);
+        }
+        if i + 1 >= length {
+            let center = i + 1 - length;
+            while window.front().is_some_and(|&j| j + length <= center) {
+                window.pop_front();
+            }
+            minimums[center] = gains[*window.front().unwrap()];
+        }
+    }
+
+    // 幅が length 以下の移動平均は、どのフレームでもそのフレームのゲイン以下になる
+    let half = length / 2;
+    let mut prefix = vec![0.0f64];
+    for minimum in minimums.iter() {
+        prefix.push(prefix.last().unwrap() + *minimum as f64);
+    }
+    let release = (-1.0 / (release.max(f32::EPSILON) * sampling_rate as f32)).exp();
+    let mut gain = 1.0f32;
+    for (i, frame) in samples.chunks_exact_mut(channels as usize).enumerate() {
+        let start = i.saturating_sub(half);
+        let end = (i + half + 1).min(frames);
+        let smoothed = ((prefix[end] - prefix[start]) / (end - start) as f64) as f32;
+        gain = smoothed.min(1.0 - (1.0 - gain) * release);
+        for sample in frame {
+            *sample *= gain;
+        }
+    }
+}
+
+/// 積分ラウドネスを目標の値にそろえ、トゥルーピークを制限する
+///
+/// 正規化する前のラウドネス（LUFS）を返す。無音の場合は何もせずに `None` を返す。
+pub fn normalize_loudness(
+    samples: &mut [f32],
+    sampling_rate: u32,
+    channels: u16,
+    options: &LoudnessOptions,
+) -> Option<f32> {
+    let loudness = integrated_loudness(samples, sampling_rate, channels)?;
+    let gain = 10f32.powf((options.target_loudness - loudness) / 20.0);
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+    limit_true_peak(
+        samples,
+        sampling_rate,
+        channels,
+        options.true_peak_limit,
+        options.lookahead,
+        options.release,
+    );
+    Some(loudness)
+}
+
+#[cfg(test)]
+mod loudness_tests {
+    use super::{integrated_loudness, normalize_loudness, true_peak, LoudnessOptions};
+
+    fn sine(frequency: f32, amplitude: f32, seconds: f32, sampling_rate: u32) -> Vec<f32> {
+        (0..(seconds * sampling_rate as f32) as usize)
+            .map(|i| {
+                amplitude
+                    * (2.0 * std::f32::consts::PI * frequency * i as f32 / sampling_rate as f32)
+                        .sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_integrated_loudness() {
+        // 1 kHz、0 dBFS の正弦波は -3.01 LUFS になる
+        let samples = sine(1000.0, 1.0, 3.0, 48000);
+        let loudness = integrated_loudness(&samples, 48000, 1).unwrap();
+        assert!((loudness + 3.01).abs() < 0.05, "{}", loudness);
+        assert_eq!(integrated_loudness(&[0.0; 48000], 48000, 1), None);
+
+        // fs/4 で位相が 45 度ずれた正弦波はサンプルの間にピークがある
+        let samples = (0..24000)
+            .map(|i| (std::f32::consts::PI * (i as f32 / 2.0 + 0.25)).sin())
+            .collect::<Vec<_>>();
+        let peak = true_peak(&samples, 1);
+        assert!(peak > -0.5, "{}", peak);
+    }
+
+    #[test]
+    fn test_normalize_loudness() {
+        let mut samples = sine(440.0, 0.05, 2.0, 24000);
+        normalize_loudness(&mut samples, 24000, 1, &LoudnessOptions::podcast()).unwrap();
+        let loudness = integrated_loudness(&samples, 24000, 1).unwrap();
+        assert!((loudness + 16.0).abs() < 0.1, "{}", loudness);
+
+        // 目標のラウドネスではピークが上限を超える場合
+        let mut samples = sine(440.0, 0.05, 2.0, 24000);
+        let options = LoudnessOptions {
+            target_loudness: -3.0,
+            ..LoudnessOptions::podcast()
+        };
+        normalize_loudness(&mut samples, 24000, 1, &options).unwrap();
+        let peak = true_peak(&samples, 1);
+        assert!(peak <= -0.9, "{}", peak);
+    }
+}
@@ -0,0 +1,78 @@
+//! 話者とスタイルの情報
+//!
+//! voicevox_core の `metas` が返す JSON を読み、話者名とスタイル名から話者 ID を引く。
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StyleMeta {
+    pub name: String,
+    pub id: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeakerMeta {
+    pub name: String,
+    pub styles: Vec<StyleMeta>,
+    #[serde(default)]
+    pub speaker_uuid: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+pub fn parse_metas(json: &str) -> Result<Vec<SpeakerMeta>, String> {
+    serde_json::from_str(json).map_err(|e| format!("failed to parse metas: {}", e))
+}
+
+/// 話者名とスタイル名から話者 ID を引く
+///
+/// スタイル名を省略した場合はその話者の最初のスタイルを使う。
+/// `speaker` が数字の場合はそのまま話者 ID として扱う。
+pub fn find_style_id(
+    metas: &[SpeakerMeta],
+    speaker: &str,
+    style: Option<&str>,
+) -> Result<i64, String> {
+    if style.is_none() {
+        if let Ok(id) = speaker.parse::<i64>() {
+            return Ok(id);
+        }
+    }
+    let speaker_meta = metas
+        .iter()
+        .find(|meta| meta.name == speaker)
+        .ok_or_else(|| format!("speaker `{}` is not found", speaker))?;
+    let style_meta = match style {
+        Some(style) => speaker_meta
+            .styles
+            .iter()
+            .find(|meta| meta.name == style)
+            .ok_or_else(|| format!("style `{}` of speaker `{}` is not found", style, speaker))?,
+        None => speaker_meta
+            .styles
+            .first()
+            .ok_or_else(|| format!("speaker `{}` has no styles", speaker))?,
+    };
+    Ok(style_meta.id)
+}
+
+#[cfg(test)]
+mod metas_tests {
+    use super::{find_style_id, parse_metas};
+
+    #[test]
+    fn test_find_style_id() {
+        let metas = parse_metas(
+            r#"[{"name":"四国めたん","styles":[{"name":"ノーマル","id":2},{"name":"あまあま","id":0}],
+                "speaker_uuid":"7ffcb7ce-00ec-4bdc-82cd-45a8889e43ff","version":"0.11.4"},
+               {"name":"ずんだもん","styles":[{"name":"ノーマル","id":3}]}]"#,
+        )
+        .unwrap();
+        assert_eq!(find_style_id(&metas, "四国めたん", None), Ok(2));
+        assert_eq!(find_style_id(&metas, "四国めたん", Some("あまあま")), Ok(0));
+        assert_eq!(find_style_id(&metas, "ずんだもん", None), Ok(3));
+        assert_eq!(find_style_id(&metas, "8", None), Ok(8));
+        assert!(find_style_id(&metas, "ずんだもん", Some("ささやき")).is_err());
+        assert!(find_style_id(&metas, "春日部つむぎ", None).is_err());
+    }
+}
@@ -1,4 +1,8 @@
-#[derive(Debug, Clone)]
+//! VOICEVOX ENGINE の AudioQuery と同じ形式で JSON に変換できる
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MoraModel {
     pub text: String,
     pub consonant: Option<String>,
@@ -8,16 +12,81 @@ pub struct MoraModel {
     pub pitch: f32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccentPhraseModel {
     pub moras: Vec<MoraModel>,
     pub accent: u32,
     pub pause_mora: Option<MoraModel>,
+    #[serde(default)]
     pub is_interrogative: bool,
 }
 
-#[derive(Debug, Clone)]
+impl AccentPhraseModel {
+    /// 二つのアクセント句をつなげる
+    ///
+    /// つなげ方は [`merge_accent_phrase`] に従う。ポーズと疑問文かどうかは、
+    /// 文末の側にある後ろのアクセント句のものを使う。
+    pub fn merge(&self, accent_phrase: &AccentPhraseModel) -> AccentPhraseModel {
+        let (moras, accent) = merge_accent_phrase(
+            (&self.moras, self.accent),
+            (&accent_phrase.moras, accent_phrase.accent),
+        );
+        AccentPhraseModel {
+            moras,
+            accent,
+            pause_mora: accent_phrase.pause_mora.clone(),
+            is_interrogative: accent_phrase.is_interrogative,
+        }
+    }
+
+    /// `mora` 番目のモーラの前でアクセント句を分ける
+    ///
+    /// アクセント核を含まない側は平板型になる。
+    pub fn split_at(&self, mora: usize) -> Result<(AccentPhraseModel, AccentPhraseModel), String> {
+        if mora == 0 || mora >= self.moras.len() {
+            return Err(format!(
+                "split position must be in 1..{}, but got {}",
+                self.moras.len(),
+                mora
+            ));
+        }
+        let accent = self.accent as usize;
+        let former = AccentPhraseModel {
+            moras: self.moras[..mora].to_vec(),
+            accent: accent.min(mora) as u32,
+            pause_mora: None,
+            is_interrogative: false,
+        };
+        let latter_moras = self.moras[mora..].to_vec();
+        let latter = AccentPhraseModel {
+            accent: if accent > mora {
+                (accent - mora) as u32
+            } else {
+                latter_moras.len() as u32
+            },
+            moras: latter_moras,
+            pause_mora: self.pause_mora.clone(),
+            is_interrogative: self.is_interrogative,
+        };
+        Ok((former, latter))
+    }
+}
+
+/// アクセント句をつなげる規則。引数はモーラとアクセント位置
+///
+/// モーラは順に並べ、アクセント核は前のアクセント句のものを使う。[`AccentPhraseModel::merge`] と
+/// [`AccentPhrase::merge`](crate::full_context_label::accent_phrase::AccentPhrase::merge) で共通。
+pub(crate) fn merge_accent_phrase<M: Clone>(
+    (former_moras, former_accent): (&[M], u32),
+    (latter_moras, _): (&[M], u32),
+) -> (Vec<M>, u32) {
+    ([former_moras, latter_moras].concat(), former_accent)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AudioQueryModel {
+    #[serde(rename = "accent_phrases")]
     pub accent_phrases: Vec<AccentPhraseModel>,
     pub speed_scale: f32,
     pub pitch_scale: f32,
@@ -27,5 +96,60 @@ pub struct AudioQueryModel {
     pub post_phoneme_length: f32,
     pub output_sampling_rate: u32,
     pub output_stereo: bool,
+    /// ENGINE では省略可能なため、無い場合や `null` の場合は空文字列として読み込む
+    #[serde(default, deserialize_with = "deserialize_nullable_string")]
     pub kana: String,
 }
+
+fn deserialize_nullable_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod model_tests {
+    use super::{merge_accent_phrase, AudioQueryModel};
+    use crate::{
+        direct_input::accent_phrases_from_kana, full_context_label::accent_phrase::AccentPhrase,
+    };
+
+    #[test]
+    fn test_merge_and_split() {
+        let accent_phrases = accent_phrases_from_kana("コ'レワ、ナ'ンデスカ？").unwrap();
+        let merged = accent_phrases[0].merge(&accent_phrases[1]);
+        assert_eq!(merged.moras.len(), 8);
+        assert_eq!(merged.accent, 1);
+        assert!(merged.is_interrogative);
+        assert!(merged.pause_mora.is_none());
+
+        let (former, latter) = merged.split_at(3).unwrap();
+        assert_eq!((former.accent, latter.accent), (1, 5));
+        assert!(!former.is_interrogative && latter.is_interrogative);
+        assert!(merged.split_at(8).is_err());
+
+        assert_eq!(
+            merge_accent_phrase((&[1, 2], 2), (&[3], 1)),
+            (vec![1, 2, 3], 2)
+        );
+        // フルコンテキストラベルのアクセント句は、疑問文かどうかを前のアクセント句から取る
+        let merged =
+            AccentPhrase::new(Vec::new(), 1, true).merge(&AccentPhrase::new(Vec::new(), 2, false));
+        assert_eq!(merged.accent, 1);
+        assert!(merged.is_interrogative);
+    }
+
+    #[test]
+    fn test_audio_query_json_round_trip() {
+        let json = r#"{"accent_phrases":[{"moras":[{"text":"コ","consonant":"k","consonant_length":0.1,"vowel":"o","vowel_length":0.2,"pitch":5.5},{"text":"ン","consonant":null,"consonant_length":null,"vowel":"N","vowel_length":0.1,"pitch":5.0}],"accent":1,"pause_mora":{"text":"、","consonant":null,"consonant_length":null,"vowel":"pau","vowel_length":0.3,"pitch":0.0},"is_interrogative":false}],"speedScale":1.0,"pitchScale":0.0,"intonationScale":1.0,"volumeScale":1.0,"prePhonemeLength":0.1,"postPhonemeLength":0.1,"outputSamplingRate":24000,"outputStereo":false,"kana":"コ'ン、"}"#;
+        let audio_query: AudioQueryModel = serde_json::from_str(json).unwrap();
+        assert_eq!(audio_query.accent_phrases[0].moras[1].consonant, None);
+        assert_eq!(audio_query.output_sampling_rate, 24000);
+        assert_eq!(serde_json::to_string(&audio_query).unwrap(), json);
+
+        let json = r#"{"accent_phrases":[],"speedScale":1.0,"pitchScale":0.0,"intonationScale":1.0,"volumeScale":1.0,"prePhonemeLength":0.1,"postPhonemeLength":0.1,"outputSamplingRate":24000,"outputStereo":false,"kana":null}"#;
+        let audio_query: AudioQueryModel = serde_json::from_str(json).unwrap();
+        assert_eq!(audio_query.kana, "");
+    }
+}
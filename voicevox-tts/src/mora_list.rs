@@ -42,6 +42,11 @@
  * POSSIBILITY OF SUCH DAMAGE.
  */
 
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+
+use crate::model::MoraModel;
+
 #[rustfmt::skip]
 pub const MORA_LIST_MINIMUM: &[&str] = &[
     "ヴォ", "v", "o",
@@ -190,6 +195,40 @@ pub const MORA_LIST_MINIMUM: &[&str] = &[
     "ア", "", "a",
 ];
 
+#[rustfmt::skip]
+pub const MORA_LIST_ADDITIONAL: &[&str] = &[
+    "ヴョ", "by", "o",
+    "ヴュ", "by", "u",
+    "ヴャ", "by", "a",
+    "ヲ", "", "o",
+    "ヱ", "", "e",
+    "ヰ", "", "i",
+    "ヮ", "w", "a",
+    "ョ", "y", "o",
+    "ュ", "y", "u",
+    "ヅ", "z", "u",
+    "ヂ", "j", "i",
+    "ヶ", "k", "e",
+    "ャ", "y", "a",
+    "ォ", "", "o",
+    "ェ", "", "e",
+    "ゥ", "", "u",
+    "ィ", "", "i",
+    "ァ", "", "a",
+];
+
+pub(crate) fn text2mora_map() -> &'static HashMap<&'static str, (&'static str, &'static str)> {
+    static TEXT2MORA_MAP: OnceCell<HashMap<&'static str, (&'static str, &'static str)>> =
+        OnceCell::new();
+    TEXT2MORA_MAP.get_or_init(|| {
+        MORA_LIST_MINIMUM
+            .chunks(3)
+            .chain(MORA_LIST_ADDITIONAL.chunks(3))
+            .map(|mora| (mora[0], (mora[1], mora[2])))
+            .collect()
+    })
+}
+
 pub fn mora2text(mora: String) -> String {
     let mut count = 1;
     while count < MORA_LIST_MINIMUM.len() {
@@ -205,3 +244,115 @@ pub fn mora2text(mora: String) -> String {
     }
     mora
 }
+
+/// ひらがなをカタカナに変換する
+pub fn hiragana2katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60).unwrap(),
+            _ => c,
+        })
+        .collect()
+}
+
+/// カタカナまたはひらがなのテキストを、最長一致でモーラに分ける
+///
+/// 長音符「ー」は直前のモーラの母音として扱う。
+/// 作られるモーラの長さと音高は 0 である。
+pub fn kana2moras(kana: &str) -> Result<Vec<MoraModel>, String> {
+    let chars = hiragana2katakana(kana).chars().collect::<Vec<_>>();
+    let map = text2mora_map();
+
+    let mut moras: Vec<MoraModel> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == 'ー' {
+            let vowel = match moras.last() {
+                Some(mora) if "aiueo".contains(mora.vowel.as_str()) => mora.vowel.clone(),
+                _ => return Err(format!("unexpected long vowel mark at {}", i)),
+            };
+            moras.push(MoraModel {
+                text: mora2text(vowel.clone()),
+                consonant: None,
+                consonant_length: None,
+                vowel,
+                vowel_length: 0.0,
+                pitch: 0.0,
+            });
+            i += 1;
+            continue;
+        }
+
+        let found = [2, 1].iter().find_map(|&len| {
+            if i + len > chars.len() {
+                return None;
+            }
+            let text = chars[i..i + len].iter().collect::<String>();
+            map.get(text.as_str()).map(|mora| (len, text, mora))
+        });
+        let (len, text, (consonant, vowel)) = match found {
+            Some(found) => found,
+            None => return Err(format!("unknown kana `{}` at {}", chars[i], i)),
+        };
+        moras.push(MoraModel {
+            text,
+            consonant: if consonant.is_empty() {
+                None
+            } else {
+                Some(consonant.to_string())
+            },
+            consonant_length: if consonant.is_empty() {
+                None
+            } else {
+                Some(0.0)
+            },
+            vowel: vowel.to_string(),
+            vowel_length: 0.0,
+            pitch: 0.0,
+        });
+        i += len;
+    }
+    Ok(moras)
+}
+
+/// カタカナの発音に含まれるモーラの数を数える
+///
+/// 拗音などの小書き文字は直前の文字と合わせて一つのモーラとし、
+/// カタカナ以外の文字（無声化の記号 `’` など）は数えない。
+/// ヶは [`MORA_LIST_ADDITIONAL`] と同じく「ケ」の一モーラとして数える。
+pub fn count_moras(pron: &str) -> usize {
+    pron.chars()
+        .filter(|c| ('ァ'..='ヴ').contains(c) || *c == 'ヶ' || *c == 'ー')
+        .filter(|c| !"ァィゥェォャュョヮ".contains(*c))
+        .count()
+}
+
+#[cfg(test)]
+mod mora_list_tests {
+    use super::{count_moras, kana2moras};
+
+    #[test]
+    fn test_kana2moras() {
+        let moras = kana2moras("きょうはラーメン").unwrap();
+        let texts = moras
+            .iter()
+            .map(|mora| mora.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["キョ", "ウ", "ハ", "ラ", "ア", "メ", "ン"]);
+        assert_eq!(moras[0].consonant.as_deref(), Some("ky"));
+        assert_eq!(moras[4].vowel, "a");
+        assert!(kana2moras("ーア").is_err());
+        assert!(kana2moras("漢字").is_err());
+    }
+
+    #[test]
+    fn test_count_moras() {
+        assert_eq!(count_moras("ガッコー"), 4);
+        assert_eq!(count_moras("キョウ"), 2);
+        assert_eq!(count_moras("、"), 0);
+        assert_eq!(
+            count_moras("イッヶゲツ"),
+            kana2moras("イッヶゲツ").unwrap().len()
+        );
+    }
+}
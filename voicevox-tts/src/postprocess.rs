@@ -0,0 +1,204 @@
+//! 合成した音声の後処理
+//!
+//! 無音の切り詰め、フェード、直流成分の除去、ハイパスフィルター、つなぎ目のクリック除去を、
+//! 指定した順にかける。ステレオの場合は左右のサンプルが交互に並んだ音声を扱う。
+
+use std::f32::consts::PI;
+
+/// 後処理の一段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostProcess {
+    /// 先頭と末尾の、振幅が `threshold`（dBFS）以下の部分を、`padding` 秒だけ残して取り除く
+    Trim { threshold: f32, padding: f32 },
+    /// 先頭と末尾のフェード（秒）
+    Fade { fade_in: f32, fade_out: f32 },
+    /// 直流成分を取り除く
+    RemoveDc,
+    /// `cutoff`（Hz）より低い音を落とす 2 次のバターワースフィルター
+    HighPass { cutoff: f32 },
+    /// 文や行のつなぎ目の前後を `length` 秒でフェードし、音の途切れによるクリックを防ぐ
+    Declick { length: f32 },
+}
+
+// 直流成分の除去に使うハイパスのカットオフ（Hz）
+const DC_CUTOFF: f32 = 10.0;
+
+fn trim(
+    samples: &mut Vec<f32>,
+    sampling_rate: u32,
+    channels: usize,
+    threshold: f32,
+    padding: f32,
+) -> usize {
+    let amplitude = 10f32.powf(threshold / 20.0);
+    let frames = samples.len() / channels;
+    let is_sound = |frame: &[f32]| frame.iter().any(|sample| sample.abs() > amplitude);
+    let first = samples.chunks_exact(channels).position(is_sound);
+    let last = samples.chunks_exact(channels).rposition(is_sound);
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            samples.clear();
+            return frames;
+        }
+    };
+    let padding = (padding.max(0.0) * sampling_rate as f32).round() as usize;
+    let start = first.saturating_sub(padding);
+    let end = (last + 1 + padding).min(frames);
+    samples.truncate(end * channels);
+    samples.drain(..start * channels);
+    start
+}
+
+// 0 から 1 まで滑らかに上がるフェードの曲線
+fn fade_curve(i: usize, length: usize) -> f32 {
+    0.5 - 0.5 * (PI * (i as f32 + 0.5) / length as f32).cos()
+}
+
+fn fade(samples: &mut [f32], channels: usize, start: usize, length: usize, fade_in: bool) {
+    let frames = samples.len() / channels;
+    for i in 0..length.min(frames.saturating_sub(start)) {
+        let gain = if fade_in {
+            fade_curve(i, length)
+        } else {
+            fade_curve(length - 1 - i, length)
+        };
+        for sample in &mut samples[(start + i) * channels..(start + i + 1) * channels] {
+            *sample *= gain;
+        }
+    }
+}
+
+/// RBJ の 2 次のハイパスフィルターを各チャンネルにかける
+fn high_pass(samples: &mut [f32], sampling_rate: u32, channels: usize, cutoff: f32) {
+    let omega = 2.0 * PI * cutoff / sampling_rate as f32;
+    let alpha = omega.sin() / 2.0 * std::f32::consts::SQRT_2;
+    let a0 = 1.0 + alpha;
+    let b = [
+        (1.0 + omega.cos()) / 2.0 / a0,
+        -(1.0 + omega.cos()) / a0,
+        (1.0 + omega.cos()) / 2.0 / a0,
+    ];
+    let a = [-2.0 * omega.cos() / a0, (1.0 - alpha) / a0];
+    for channel in 0..channels {
+        let mut z = [0.0f32; 2];
+        for sample in samples.iter_mut().skip(channel).step_by(channels) {
+            let x = *sample;
+            let y = b[0] * x + z[0];
+            z[0] = b[1] * x - a[0] * y + z[1];
+            z[1] = b[2] * x - a[1] * y;
+            *sample = y;
+        }
+    }
+}
+
+/// 一次の直流阻止フィルター。無音の部分は無音のまま残る
+fn remove_dc(samples: &mut [f32], sampling_rate: u32, channels: usize) {
+    let r = (-2.0 * PI * DC_CUTOFF / sampling_rate as f32).exp();
+    for channel in 0..channels {
+        let (mut previous_x, mut previous_y) = (0.0f32, 0.0f32);
+        for sample in samples.iter_mut().skip(channel).step_by(channels) {
+            let y = *sample - previous_x + r * previous_y;
+            previous_x = *sample;
+            previous_y = y;
+            *sample = y;
+        }
+    }
+}
+
+/// 後処理を順にかけ、先頭から取り除いたフレーム数を返す
+///
+/// `joins` はつなぎ目のフレームの位置で、[`PostProcess::Declick`] で使う。
+pub fn post_process(
+    samples: &mut Vec<f32>,
+    sampling_rate: u32,
+    channels: u16,
+    stages: &[PostProcess],
+    joins: &[usize],
+) -> usize {
+    let channels = channels as usize;
+    let seconds_to_frames =
+        |seconds: f32| (seconds.max(0.0) * sampling_rate as f32).round() as usize;
+    let mut removed = 0;
+    for stage in stages {
+        match *stage {
+            PostProcess::Trim { threshold, padding } => {
+                removed += trim(samples, sampling_rate, channels, threshold, padding);
+            }
+            PostProcess::Fade { fade_in, fade_out } => {
+                let frames = samples.len() / channels;
+                let fade_in = seconds_to_frames(fade_in).min(frames);
+                let fade_out = seconds_to_frames(fade_out).min(frames);
+                fade(samples, channels, 0, fade_in, true);
+                fade(samples, channels, frames - fade_out, fade_out, false);
+            }
+            PostProcess::RemoveDc => remove_dc(samples, sampling_rate, channels),
+            PostProcess::HighPass { cutoff } => high_pass(samples, sampling_rate, channels, cutoff),
+            PostProcess::Declick { length } => {
+                let length = seconds_to_frames(length);
+                let frames = samples.len() / channels;
+                for join in joins {
+                    let join = match join.checked_sub(removed) {
+                        Some(join) if join > 0 && join < frames => join,
+                        _ => continue,
+                    };
+                    let before = length.min(join);
+                    fade(samples, channels, join - before, before, false);
+                    fade(samples, channels, join, length, true);
+                }
+            }
+        }
+    }
+    removed
+}
+
+/// 先頭を取り除いた後の時刻（秒）
+pub(crate) fn shift_time(time: f32, removed: f32, duration: f32) -> f32 {
+    (time - removed).clamp(0.0, duration)
+}
+
+#[cfg(test)]
+mod postprocess_tests {
+    use super::{post_process, PostProcess};
+
+    #[test]
+    fn test_post_process() {
+        // 0.1 秒の無音、直流成分を含む 0.5 秒の音、0.2 秒の無音
+        let sampling_rate = 1000;
+        let mut samples = vec![0.0; 100];
+        samples.extend((0..500).map(|i| 0.2 + 0.5 * (i as f32 * 0.7).sin()));
+        samples.extend(vec![0.0; 200]);
+
+        let stages = [
+            PostProcess::Trim {
+                threshold: -40.0,
+                padding: 0.01,
+            },
+            PostProcess::RemoveDc,
+            PostProcess::Fade {
+                fade_in: 0.02,
+                fade_out: 0.02,
+            },
+            PostProcess::Declick { length: 0.005 },
+        ];
+        let removed = post_process(&mut samples, sampling_rate, 1, &stages, &[350]);
+        assert_eq!(removed, 90);
+        assert_eq!(samples.len(), 520);
+        assert!(samples[0].abs() < 1e-3 && samples[519].abs() < 1e-3);
+        // つなぎ目（先頭を取り除いた後の 260）の前後は 0 に近づく
+        assert!(samples[259].abs() < 0.05 && samples[260].abs() < 0.05);
+        let mean = samples[100..500].iter().sum::<f32>() / 400.0;
+        assert!(mean.abs() < 0.05, "{}", mean);
+
+        let mut silence = vec![0.0; 100];
+        let stages = [PostProcess::Trim {
+            threshold: -40.0,
+            padding: 0.0,
+        }];
+        assert_eq!(
+            post_process(&mut silence, sampling_rate, 1, &stages, &[]),
+            100
+        );
+        assert!(silence.is_empty());
+    }
+}
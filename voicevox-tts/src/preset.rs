@@ -0,0 +1,257 @@
+//! 話者と合成パラメータの組を名前を付けて保存する
+//!
+//! VOICEVOX ENGINE のプリセットに相当する。ファイルの拡張子が `.toml` の場合は TOML、
+//! それ以外の場合は JSON（プリセットの配列）として読み書きする。
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio_query::{
+        check_range, INTONATION_SCALE_RANGE, PHONEME_LENGTH_RANGE, PITCH_SCALE_RANGE,
+        SPEED_SCALE_RANGE, VOLUME_SCALE_RANGE,
+    },
+    model::AudioQueryModel,
+};
+
+/// ポーズの長さの倍率
+pub const PAUSE_LENGTH_SCALE_RANGE: std::ops::RangeInclusive<f32> = 0.0..=2.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub id: i64,
+    pub name: String,
+    pub speaker_id: i64,
+    pub speed_scale: f32,
+    pub pitch_scale: f32,
+    pub intonation_scale: f32,
+    pub volume_scale: f32,
+    pub pre_phoneme_length: f32,
+    pub post_phoneme_length: f32,
+    #[serde(default = "default_pause_length_scale")]
+    pub pause_length_scale: f32,
+}
+
+fn default_pause_length_scale() -> f32 {
+    1.0
+}
+
+impl Preset {
+    /// 合成パラメータが標準の値のプリセットを作る。`id` は [`PresetManager::add`] で振り直される
+    pub fn new(name: String, speaker_id: i64) -> Preset {
+        Preset {
+            id: 0,
+            name,
+            speaker_id,
+            speed_scale: 1.0,
+            pitch_scale: 0.0,
+            intonation_scale: 1.0,
+            volume_scale: 1.0,
+            pre_phoneme_length: 0.1,
+            post_phoneme_length: 0.1,
+            pause_length_scale: default_pause_length_scale(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("preset name must not be empty".to_string());
+        }
+        check_range("speed_scale", self.speed_scale, SPEED_SCALE_RANGE)?;
+        check_range("pitch_scale", self.pitch_scale, PITCH_SCALE_RANGE)?;
+        check_range(
+            "intonation_scale",
+            self.intonation_scale,
+            INTONATION_SCALE_RANGE,
+        )?;
+        check_range("volume_scale", self.volume_scale, VOLUME_SCALE_RANGE)?;
+        check_range(
+            "pre_phoneme_length",
+            self.pre_phoneme_length,
+            PHONEME_LENGTH_RANGE,
+        )?;
+        check_range(
+            "post_phoneme_length",
+            self.post_phoneme_length,
+            PHONEME_LENGTH_RANGE,
+        )?;
+        check_range(
+            "pause_length_scale",
+            self.pause_length_scale,
+            PAUSE_LENGTH_SCALE_RANGE,
+        )
+    }
+
+    /// クエリの合成パラメータをプリセットの値で置き換え、ポーズの長さに倍率をかける
+    pub fn apply(&self, query: &mut AudioQueryModel) {
+        query.speed_scale = self.speed_scale;
+        query.pitch_scale = self.pitch_scale;
+        query.intonation_scale = self.intonation_scale;
+        query.volume_scale = self.volume_scale;
+        query.pre_phoneme_length = self.pre_phoneme_length;
+        query.post_phoneme_length = self.post_phoneme_length;
+        for accent_phrase in query.accent_phrases.iter_mut() {
+            if let Some(ref mut pause_mora) = accent_phrase.pause_mora {
+                pause_mora.vowel_length *= self.pause_length_scale;
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TomlPresets {
+    #[serde(default)]
+    presets: Vec<Preset>,
+}
+
+/// プリセットの一覧を管理する
+///
+/// ファイルから読み込んだ場合は、変更のたびにファイルへ書き出す。
+#[derive(Debug, Clone, Default)]
+pub struct PresetManager {
+    path: Option<PathBuf>,
+    presets: Vec<Preset>,
+}
+
+impl PresetManager {
+    /// ファイルに保存しないプリセットの一覧を作る
+    pub fn new() -> PresetManager {
+        PresetManager::default()
+    }
+
+    /// ファイルからプリセットを読み込む。ファイルが無い場合は空の一覧になる
+    pub fn load(path: &Path) -> Result<PresetManager, String> {
+        let presets = if path.exists() {
+            let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+            if is_toml(path) {
+                toml::from_str::<TomlPresets>(&content)
+                    .map_err(|e| e.to_string())?
+                    .presets
+            } else {
+                serde_json::from_str(&content).map_err(|e| e.to_string())?
+            }
+        } else {
+            Vec::new()
+        };
+        for preset in presets.iter() {
+            preset
+                .validate()
+                .map_err(|e| format!("preset `{}`: {}", preset.name, e))?;
+        }
+        Ok(PresetManager {
+            path: Some(path.to_path_buf()),
+            presets,
+        })
+    }
+
+    pub fn presets(&self) -> &[Preset] {
+        &self.presets
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+
+    /// プリセットを追加し、振られた id を返す
+    pub fn add(&mut self, preset: Preset) -> Result<i64, String> {
+        preset.validate()?;
+        if self.get(&preset.name).is_some() {
+            return Err(format!("preset `{}` already exists", preset.name));
+        }
+        let id = self
+            .presets
+            .iter()
+            .map(|preset| preset.id + 1)
+            .max()
+            .unwrap_or(1);
+        self.presets.push(Preset { id, ..preset });
+        self.save()?;
+        Ok(id)
+    }
+
+    /// 同じ id のプリセットを置き換える
+    pub fn update(&mut self, preset: Preset) -> Result<(), String> {
+        preset.validate()?;
+        if self
+            .presets
+            .iter()
+            .any(|p| p.name == preset.name && p.id != preset.id)
+        {
+            return Err(format!("preset `{}` already exists", preset.name));
+        }
+        match self.presets.iter_mut().find(|p| p.id == preset.id) {
+            Some(p) => *p = preset,
+            None => return Err(format!("preset id {} is not found", preset.id)),
+        }
+        self.save()
+    }
+
+    pub fn delete(&mut self, id: i64) -> Result<(), String> {
+        let len = self.presets.len();
+        self.presets.retain(|preset| preset.id != id);
+        if self.presets.len() == len {
+            return Err(format!("preset id {} is not found", id));
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let content = if is_toml(path) {
+            toml::to_string(&TomlPresets {
+                presets: self.presets.clone(),
+            })
+            .map_err(|e| e.to_string())?
+        } else {
+            serde_json::to_string_pretty(&self.presets).map_err(|e| e.to_string())?
+        };
+        fs::write(path, content).map_err(|e| e.to_string())
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+#[cfg(test)]
+mod preset_tests {
+    use super::{Preset, PresetManager};
+
+    #[test]
+    fn test_preset_manager() {
+        for extension in ["json", "toml"] {
+            let path = std::env::temp_dir().join(format!(
+                "voicevox-tts-presets-{}.{}",
+                std::process::id(),
+                extension
+            ));
+            let mut presets = PresetManager::load(&path).unwrap();
+            let id = presets.add(Preset::new("narrator".to_string(), 2)).unwrap();
+            assert!(presets.add(Preset::new("narrator".to_string(), 3)).is_err());
+            let other = presets.add(Preset::new("other".to_string(), 3)).unwrap();
+            assert_ne!(id, other);
+
+            let mut preset = presets.get("narrator").unwrap().clone();
+            preset.speed_scale = 1.2;
+            presets.update(preset).unwrap();
+            preset = presets.get("narrator").unwrap().clone();
+            preset.speed_scale = 3.0;
+            assert!(presets.update(preset).is_err());
+            presets.delete(other).unwrap();
+
+            let loaded = PresetManager::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.presets(), presets.presets());
+            assert_eq!(loaded.get("narrator").unwrap().speed_scale, 1.2);
+        }
+    }
+}
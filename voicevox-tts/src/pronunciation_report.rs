@@ -0,0 +1,289 @@
+use openjtalk::{NjdFeature, OpenJTalk};
+
+use crate::{full_context_label::extract_fullcontext_from_features, mora_list::count_moras};
+
+// 前の形態素の終わりから、次の形態素の表層形を探す範囲（文字数）
+const SEARCH_WINDOW: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportKind {
+    /// MeCab の辞書に無い語。読みが `None` の場合は読み上げられない
+    UnknownWord,
+    /// 数字を読みに展開した箇所
+    NumberExpansion,
+    /// 記号やアルファベットを読みに展開した箇所
+    SymbolExpansion,
+    /// アクセント位置がモーラ数を超えていたため丸められたアクセント句
+    AccentClamped { label_accent: u32, accent: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportItem {
+    pub kind: ReportKind,
+    /// 入力テキスト中の開始位置（文字単位）
+    pub start: usize,
+    /// 入力テキスト中の終了位置（文字単位、この位置は含まない）
+    pub end: usize,
+    pub text: String,
+    pub reading: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PronunciationReport {
+    pub items: Vec<ReportItem>,
+}
+
+impl PronunciationReport {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// テキストを解析し、未知語・数字や記号の展開・アクセント位置の丸めを報告する
+pub fn analyze(openjtalk: OpenJTalk, text: &str) -> Result<PronunciationReport, String> {
+    let original_chars = text.chars().collect::<Vec<_>>();
+    // text2mecab と同様に半角文字を全角文字に揃えてから照合する
+    let chars = original_chars
+        .iter()
+        .map(|c| to_zenkaku(*c))
+        .collect::<Vec<_>>();
+    let slice = |start: usize, end: usize| original_chars[start..end].iter().collect::<String>();
+
+    let morphemes = openjtalk.run_mecab(text)?;
+    let features = openjtalk.run_frontend(text)?;
+    let morpheme_spans = align(&chars, morphemes.iter().map(|m| m.surface.as_str()));
+    let feature_spans = align(&chars, features.iter().map(|f| f.string.as_str()));
+
+    let mut items = Vec::new();
+
+    for (morpheme, &(start, end)) in morphemes.iter().zip(morpheme_spans.iter()) {
+        if !morpheme.is_unknown {
+            continue;
+        }
+        let reading = features
+            .iter()
+            .zip(feature_spans.iter())
+            .filter(|(_, &(s, e))| s < e && start <= s && e <= end)
+            .map(|(feature, _)| feature.pron.as_str())
+            .filter(|pron| count_moras(pron) > 0)
+            .collect::<String>();
+        items.push(ReportItem {
+            kind: ReportKind::UnknownWord,
+            start,
+            end,
+            text: morpheme.surface.clone(),
+            reading: if reading.is_empty() {
+                None
+            } else {
+                Some(reading)
+            },
+        });
+    }
+
+    let mut i = 0;
+    while i < features.len() {
+        if features[i].pos_group1 == "数" {
+            let mut j = i;
+            while j < features.len() && features[j].pos_group1 == "数" {
+                j += 1;
+            }
+            let (start, end) = covering_span(&feature_spans[i..j]);
+            let number_text = slice(start, end);
+            if number_text.chars().any(is_digit) {
+                items.push(ReportItem {
+                    kind: ReportKind::NumberExpansion,
+                    start,
+                    end,
+                    text: number_text,
+                    reading: Some(features[i..j].iter().map(|f| f.pron.as_str()).collect()),
+                });
+            }
+            i = j;
+        } else {
+            let feature = &features[i];
+            let (start, end) = feature_spans[i];
+            if feature.pos == "記号" && count_moras(&feature.pron) > 0 {
+                items.push(ReportItem {
+                    kind: ReportKind::SymbolExpansion,
+                    start,
+                    end,
+                    text: slice(start, end),
+                    reading: Some(feature.pron.clone()),
+                });
+            }
+            i += 1;
+        }
+    }
+
+    items.append(&mut clamped_accent_items(
+        openjtalk,
+        &features,
+        &feature_spans,
+        &original_chars,
+    )?);
+
+    items.sort_by_key(|item| (item.start, item.end));
+    Ok(PronunciationReport { items })
+}
+
+fn clamped_accent_items(
+    openjtalk: OpenJTalk,
+    features: &[NjdFeature],
+    feature_spans: &[(usize, usize)],
+    original_chars: &[char],
+) -> Result<Vec<ReportItem>, String> {
+    if features.is_empty() {
+        return Ok(Vec::new());
+    }
+    let utterance = extract_fullcontext_from_features(openjtalk, features)?;
+
+    // 各素性が何モーラ目から何モーラ目までを占めるか
+    let mut feature_mora_ranges = Vec::new();
+    let mut mora_count = 0;
+    for feature in features.iter() {
+        let size = count_moras(&feature.pron);
+        feature_mora_ranges.push((mora_count, mora_count + size));
+        mora_count += size;
+    }
+
+    let mut items = Vec::new();
+    let mut mora_start = 0;
+    for accent_phrase in utterance
+        .breath_groups
+        .iter()
+        .flat_map(|breath_group| breath_group.accent_phrases.iter())
+    {
+        let mora_end = mora_start + accent_phrase.moras.len();
+        if accent_phrase.is_accent_clamped() {
+            let spans = feature_mora_ranges
+                .iter()
+                .zip(feature_spans.iter())
+                .filter(|(&(s, e), _)| s < e && s < mora_end && mora_start < e)
+                .map(|(_, span)| *span)
+                .collect::<Vec<_>>();
+            let (start, end) = covering_span(&spans);
+            let label_accent = accent_phrase.moras[0]
+                .vowel
+                .contexts
+                .get("f2")
+                .and_then(|f2| f2.parse().ok())
+                .unwrap_or(accent_phrase.accent);
+            items.push(ReportItem {
+                kind: ReportKind::AccentClamped {
+                    label_accent,
+                    accent: accent_phrase.accent,
+                },
+                start,
+                end,
+                text: original_chars[start..end].iter().collect(),
+                reading: None,
+            });
+        }
+        mora_start = mora_end;
+    }
+    Ok(items)
+}
+
+/// 各文字列がテキストのどこに現れるかを先頭から順に探す
+///
+/// 見つからない文字列（数字の読みの展開で挿入された「十」など）は、
+/// 直前の文字列の終わりに長さ 0 の範囲として置く。
+fn align<'a>(chars: &[char], strings: impl Iterator<Item = &'a str>) -> Vec<(usize, usize)> {
+    let mut cursor = 0;
+    strings
+        .map(|string| {
+            let target = string.chars().collect::<Vec<_>>();
+            if target.is_empty() {
+                return (cursor, cursor);
+            }
+            let found = (cursor..chars.len())
+                .take(SEARCH_WINDOW)
+                .take_while(|i| i + target.len() <= chars.len())
+                .find(|&i| chars[i..i + target.len()] == target[..]);
+            match found {
+                Some(start) => {
+                    cursor = start + target.len();
+                    (start, cursor)
+                }
+                None => (cursor, cursor),
+            }
+        })
+        .collect()
+}
+
+fn covering_span(spans: &[(usize, usize)]) -> (usize, usize) {
+    let start = spans.iter().map(|(start, _)| *start).min().unwrap_or(0);
+    let end = spans.iter().map(|(_, end)| *end).max().unwrap_or(start);
+    (start, end)
+}
+
+fn to_zenkaku(c: char) -> char {
+    match c {
+        ' ' => '　',
+        '!'..='~' => char::from_u32(c as u32 + 0xfee0).unwrap(),
+        _ => c,
+    }
+}
+
+fn is_digit(c: char) -> bool {
+    ('０'..='９').contains(&to_zenkaku(c))
+}
+
+#[cfg(test)]
+mod pronunciation_report_tests {
+    use std::collections::HashMap;
+
+    use super::{align, covering_span, to_zenkaku};
+    use crate::full_context_label::{accent_phrase::AccentPhrase, mora::Mora, phoneme::Phoneme};
+
+    fn zenkaku(text: &str) -> Vec<char> {
+        text.chars().map(to_zenkaku).collect()
+    }
+
+    #[test]
+    fn test_align_multibyte() {
+        // 位置はバイトではなく文字で数え、半角文字は全角に揃えて照合する
+        let chars = zenkaku("今日はAIです");
+        assert_eq!(chars[3], 'Ａ');
+        let spans = align(&chars, ["今日", "は", "ＡＩ", "です"].into_iter());
+        assert_eq!(spans, [(0, 2), (2, 3), (3, 5), (5, 7)]);
+    }
+
+    #[test]
+    fn test_align_number_expansion() {
+        // 数字の読みの展開で挿入された「千」「十」は長さ 0 の範囲になる
+        let chars = zenkaku("2024年");
+        let spans = align(&chars, ["２", "千", "２", "十", "４", "年"].into_iter());
+        assert_eq!(spans, [(0, 1), (1, 1), (2, 3), (3, 3), (3, 4), (4, 5)]);
+        assert_eq!(covering_span(&spans[..5]), (0, 4));
+        assert_eq!(covering_span(&[]), (0, 0));
+    }
+
+    #[test]
+    fn test_align_search_window() {
+        let chars = zenkaku("あいうえおかきくけこ");
+        // 8 文字目までは見つかる
+        assert_eq!(align(&chars, ["く"].into_iter()), [(7, 8)]);
+        // それより先は見つからず、直前の終わりに置かれる
+        assert_eq!(
+            align(&chars, ["あ", "こ", "い"].into_iter()),
+            [(0, 1), (1, 1), (1, 2)]
+        );
+        assert_eq!(align(&chars, ["あ", "け"].into_iter()), [(0, 1), (8, 9)]);
+    }
+
+    #[test]
+    fn test_is_accent_clamped() {
+        let mora = |f2: &str| {
+            let contexts = [("f2", f2), ("f3", "0"), ("a2", "1")]
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>();
+            Mora::from_vowel(Phoneme::new(contexts, String::new()))
+        };
+        let accent_phrase = |f2: &str| AccentPhrase::new(vec![mora(f2), mora(f2)], 2, false);
+        assert!(!accent_phrase("2").is_accent_clamped());
+        assert!(accent_phrase("3").is_accent_clamped());
+        assert!(!accent_phrase("xx").is_accent_clamped());
+    }
+}
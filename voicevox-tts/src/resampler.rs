@@ -0,0 +1,229 @@
+//! 帯域制限したサンプリングレート変換
+//!
+//! カイザー窓をかけた sinc 関数によるポリフェーズの変換で、任意の整数のレート間を変換できる。
+
+use std::{f64::consts::PI, ops::Range};
+
+// sinc 関数の片側の零点の数。大きいほど遷移帯域が狭くなる
+const ZERO_CROSSINGS: usize = 16;
+const KAISER_BETA: f64 = 8.0;
+// 遷移帯域のぶん、ナイキスト周波数より少し低いところで遮断する
+const CUTOFF_RATIO: f64 = 0.95;
+
+pub struct Resampler {
+    from_rate: u32,
+    to_rate: u32,
+    // 入力 from_rate / gcd サンプルごとに、出力 to_rate / gcd サンプルになる
+    up: usize,
+    down: usize,
+    // 各位相のフィルタ係数。phase_filters[p][j] は入力 i - half_taps + 1 + j に掛ける
+    phase_filters: Vec<Vec<f32>>,
+    half_taps: usize,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Result<Resampler, String> {
+        if from_rate == 0 || to_rate == 0 {
+            return Err(format!(
+                "sampling rates must be positive, but got {} and {}",
+                from_rate, to_rate
+            ));
+        }
+        let g = gcd(from_rate, to_rate);
+        let up = (to_rate / g) as usize;
+        let down = (from_rate / g) as usize;
+
+        // 入力のナイキスト周波数を 1 とした遮断周波数
+        let cutoff = (up as f64 / down as f64).min(1.0) * CUTOFF_RATIO;
+        let half_taps = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let phase_filters = (0..up)
+            .map(|phase| {
+                let frac = phase as f64 / up as f64;
+                (0..2 * half_taps)
+                    .map(|j| {
+                        // 出力の時刻から見た入力サンプルの位置
+                        let t = j as f64 - half_taps as f64 + 1.0 - frac;
+                        let window = kaiser(t / (half_taps as f64));
+                        (cutoff * sinc(cutoff * t) * window) as f32
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Resampler {
+            from_rate,
+            to_rate,
+            up,
+            down,
+            phase_filters,
+            half_taps,
+        })
+    }
+
+    pub fn from_rate(&self) -> u32 {
+        self.from_rate
+    }
+
+    pub fn to_rate(&self) -> u32 {
+        self.to_rate
+    }
+
+    /// モノラルの音声を変換する
+    pub fn process(&self, samples: &[f32]) -> Vec<f32> {
+        self.process_interleaved(samples, 1)
+    }
+
+    /// 左右などのサンプルが交互に並んだ音声を変換する
+    pub fn process_interleaved(&self, samples: &[f32], channels: usize) -> Vec<f32> {
+        let frames = samples.len() / channels;
+        self.process_part(samples, channels, 0, 0..self.output_position(frames))
+    }
+
+    /// 入力の `frame` 番目のフレームの位置に当たる、変換後のフレームの位置（切り上げ）
+    ///
+    /// 入力の `a..b` のフレームは、変換後の `output_position(a)..output_position(b)` になる。
+    pub fn output_position(&self, frame: usize) -> usize {
+        (frame * self.up).div_ceil(self.down)
+    }
+
+    /// 長い音声の一部を変換し、変換後の音声全体のうち `output` の範囲のフレームを返す
+    ///
+    /// `samples` は変換前の音声全体の `offset` フレーム目から始まる部分で、その外は無音とみなす。
+    /// 前後にフィルタの長さより長い余裕を持たせて渡せば、全体を変換した結果と一致する。
+    pub fn process_part(
+        &self,
+        samples: &[f32],
+        channels: usize,
+        offset: usize,
+        output: Range<usize>,
+    ) -> Vec<f32> {
+        let frames = samples.len() / channels;
+        if self.up == self.down {
+            let start = output.start.saturating_sub(offset).min(frames);
+            let end = output.end.saturating_sub(offset).min(frames);
+            let mut part = samples[start * channels..end * channels].to_vec();
+            part.resize(output.len() * channels, 0.0);
+            return part;
+        }
+        let mut result = Vec::with_capacity(output.len() * channels);
+        for n in output {
+            let position = n * self.down;
+            let index = position / self.up;
+            let filter = &self.phase_filters[position % self.up];
+            for channel in 0..channels {
+                let mut value = 0.0;
+                for (j, coefficient) in filter.iter().enumerate() {
+                    let i =
+                        index as isize + j as isize - self.half_taps as isize + 1 - offset as isize;
+                    if i >= 0 && (i as usize) < frames {
+                        value += samples[i as usize * channels + channel] * coefficient;
+                    }
+                }
+                result.push(value);
+            }
+        }
+        result
+    }
+}
+
+/// 音声全体のサンプリングレートを変換する
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Result<Vec<f32>, String> {
+    Ok(Resampler::new(from_rate, to_rate)?.process(samples))
+}
+
+/// 左右などのサンプルが交互に並んだ音声全体のサンプリングレートを変換する
+pub fn resample_interleaved(
+    samples: &[f32],
+    channels: usize,
+    from_rate: u32,
+    to_rate: u32,
+) -> Result<Vec<f32>, String> {
+    Ok(Resampler::new(from_rate, to_rate)?.process_interleaved(samples, channels))
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// x は -1.0 から 1.0 の範囲で窓の中に入る
+fn kaiser(x: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    bessel_i0(KAISER_BETA * (1.0 - x * x).sqrt()) / bessel_i0(KAISER_BETA)
+}
+
+// 第 1 種変形ベッセル関数 I0 の級数展開
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= half / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod resampler_tests {
+    use super::{resample, Resampler};
+
+    fn sine(frequency: f32, rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_resample_keeps_passband() {
+        for to_rate in [8000, 16000, 22050, 44100, 48000] {
+            let input = sine(1000.0, 24000, 2400);
+            let output = resample(&input, 24000, to_rate).unwrap();
+            assert_eq!(output.len(), (2400 * to_rate as usize).div_ceil(24000));
+
+            let expected = sine(1000.0, to_rate, output.len());
+            // 端はフィルタの立ち上がりがあるので中央で比べる
+            let range = output.len() / 4..output.len() * 3 / 4;
+            let error = output[range.clone()]
+                .iter()
+                .zip(expected[range].iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f32::max);
+            assert!(error < 0.01, "to_rate = {}, error = {}", to_rate, error);
+        }
+    }
+
+    #[test]
+    fn test_resample_removes_aliasing() {
+        // 8 kHz のナイキスト周波数を超える 6 kHz の音は取り除かれる
+        let input = sine(6000.0, 24000, 2400);
+        let output = resample(&input, 24000, 8000).unwrap();
+        assert!(rms(&output[200..600]) < 0.005);
+
+        let resampler = Resampler::new(24000, 48000).unwrap();
+        let stereo = input.iter().flat_map(|s| [*s, -*s]).collect::<Vec<_>>();
+        let output = resampler.process_interleaved(&stereo, 2);
+        assert_eq!(output.len(), 4800 * 2);
+        assert!((output[2000] + output[2001]).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,460 @@
+//! 形態素（NJD ノード）に対する発音の書き換え規則
+//!
+//! 規則ファイルは 1 行に 1 規則を書く。`#` 以降はコメントとして無視する。
+//!
+//! ```text
+//! # <規則名>: <条件> ... => <書き換え> ...
+//! particle_wa: surface=は pos=助詞 pos_group1=係助詞 => pron=ワ
+//! counter_nin: prev.pos_group1=数 surface=人 => read=ニン pron=ニン mora_size=2
+//! gakkou_itta: prev2.surface=学校 prev.surface=に surface=行っ => read=イッ pron=イッ
+//! ```
+//!
+//! 条件は `フィールド=値`（完全一致）または `フィールド~正規表現`（全体一致）で書く。
+//! フィールド名の前に `prev.` / `next.`（`prev2.` のように距離も指定できる）を付けると、
+//! 前後の形態素に対する条件になる。条件はすべて満たされたときに規則が適用される。
+//!
+//! フィールドには [`NjdFeature`] のフィールド名が使える。`surface` は `string` の別名である。
+//!
+//! 規則は OpenJTalk が発音を付けた直後の素性に適用され、数字の読み、アクセント句、
+//! アクセント型などはその後に決まる。`pron` を書き換えると `mora_size` は発音から数え直す。
+//! `mora_size` を明示する場合は、発音のモーラ数と一致しなければならない。
+
+use std::{fmt, fs, path::Path};
+
+use openjtalk::NjdFeature;
+use regex::Regex;
+
+use crate::mora_list::count_moras;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    String,
+    Pos,
+    PosGroup1,
+    PosGroup2,
+    PosGroup3,
+    Ctype,
+    Cform,
+    Orig,
+    Read,
+    Pron,
+    Acc,
+    MoraSize,
+    ChainRule,
+    ChainFlag,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        match name {
+            "string" | "surface" => Some(Field::String),
+            "pos" => Some(Field::Pos),
+            "pos_group1" => Some(Field::PosGroup1),
+            "pos_group2" => Some(Field::PosGroup2),
+            "pos_group3" => Some(Field::PosGroup3),
+            "ctype" => Some(Field::Ctype),
+            "cform" => Some(Field::Cform),
+            "orig" => Some(Field::Orig),
+            "read" => Some(Field::Read),
+            "pron" => Some(Field::Pron),
+            "acc" => Some(Field::Acc),
+            "mora_size" => Some(Field::MoraSize),
+            "chain_rule" => Some(Field::ChainRule),
+            "chain_flag" => Some(Field::ChainFlag),
+            _ => None,
+        }
+    }
+
+    fn is_integer(&self) -> bool {
+        matches!(self, Field::Acc | Field::MoraSize | Field::ChainFlag)
+    }
+
+    fn get(&self, feature: &NjdFeature) -> String {
+        match self {
+            Field::String => feature.string.clone(),
+            Field::Pos => feature.pos.clone(),
+            Field::PosGroup1 => feature.pos_group1.clone(),
+            Field::PosGroup2 => feature.pos_group2.clone(),
+            Field::PosGroup3 => feature.pos_group3.clone(),
+            Field::Ctype => feature.ctype.clone(),
+            Field::Cform => feature.cform.clone(),
+            Field::Orig => feature.orig.clone(),
+            Field::Read => feature.read.clone(),
+            Field::Pron => feature.pron.clone(),
+            Field::Acc => feature.acc.to_string(),
+            Field::MoraSize => feature.mora_size.to_string(),
+            Field::ChainRule => feature.chain_rule.clone(),
+            Field::ChainFlag => feature.chain_flag.to_string(),
+        }
+    }
+
+    fn set(&self, feature: &mut NjdFeature, value: &str) {
+        match self {
+            Field::String => feature.string = value.to_string(),
+            Field::Pos => feature.pos = value.to_string(),
+            Field::PosGroup1 => feature.pos_group1 = value.to_string(),
+            Field::PosGroup2 => feature.pos_group2 = value.to_string(),
+            Field::PosGroup3 => feature.pos_group3 = value.to_string(),
+            Field::Ctype => feature.ctype = value.to_string(),
+            Field::Cform => feature.cform = value.to_string(),
+            Field::Orig => feature.orig = value.to_string(),
+            Field::Read => feature.read = value.to_string(),
+            Field::Pron => feature.pron = value.to_string(),
+            // 値はパース時に整数であることを確認している
+            Field::Acc => feature.acc = value.parse().unwrap(),
+            Field::MoraSize => feature.mora_size = value.parse().unwrap(),
+            Field::ChainRule => feature.chain_rule = value.to_string(),
+            Field::ChainFlag => feature.chain_flag = value.parse().unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Equal(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    offset: isize,
+    field: Field,
+    matcher: Matcher,
+}
+
+impl Condition {
+    fn is_satisfied(&self, features: &[NjdFeature], index: usize) -> bool {
+        let target = index as isize + self.offset;
+        if target < 0 || target as usize >= features.len() {
+            return false;
+        }
+        let value = self.field.get(&features[target as usize]);
+        match &self.matcher {
+            Matcher::Equal(expected) => &value == expected,
+            Matcher::Regex(re) => re.is_match(&value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Action {
+    field: Field,
+    value: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    pub name: String,
+    conditions: Vec<Condition>,
+    actions: Vec<Action>,
+}
+
+impl RewriteRule {
+    fn parse(line: &str) -> Result<RewriteRule, String> {
+        let (name, body) = line
+            .split_once(':')
+            .ok_or_else(|| "rule name is missing".to_string())?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("rule name is empty".to_string());
+        }
+        let (conditions, actions) = body
+            .split_once("=>")
+            .ok_or_else(|| "`=>` is missing".to_string())?;
+
+        let conditions = conditions
+            .split_whitespace()
+            .map(parse_condition)
+            .collect::<Result<Vec<_>, _>>()?;
+        if conditions.is_empty() {
+            return Err("rule has no condition".to_string());
+        }
+        let actions = actions
+            .split_whitespace()
+            .map(parse_action)
+            .collect::<Result<Vec<_>, _>>()?;
+        if actions.is_empty() {
+            return Err("rule has no rewrite".to_string());
+        }
+        let value_of = |field: Field| {
+            actions
+                .iter()
+                .rev()
+                .find(|action| action.field == field)
+                .map(|action| action.value.as_str())
+        };
+        if let (Some(pron), Some(mora_size)) = (value_of(Field::Pron), value_of(Field::MoraSize)) {
+            if count_moras(pron).to_string() != mora_size {
+                return Err(format!(
+                    "mora_size={} doesn't match the {} moras of pron={}",
+                    mora_size,
+                    count_moras(pron),
+                    pron
+                ));
+            }
+        }
+
+        Ok(RewriteRule {
+            name: name.to_string(),
+            conditions,
+            actions,
+        })
+    }
+
+    fn is_match(&self, features: &[NjdFeature], index: usize) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.is_satisfied(features, index))
+    }
+
+    /// 書き換えた後の `pron` と `mora_size` が合わない場合はエラーにする
+    fn apply(&self, feature: &mut NjdFeature) -> Result<(), String> {
+        for action in self.actions.iter() {
+            action.field.set(feature, &action.value);
+        }
+        let sets = |field: Field| self.actions.iter().any(|action| action.field == field);
+        if sets(Field::Pron) && !sets(Field::MoraSize) {
+            feature.mora_size = count_moras(&feature.pron) as i32;
+        }
+        if (sets(Field::Pron) || sets(Field::MoraSize))
+            && feature.mora_size != count_moras(&feature.pron) as i32
+        {
+            return Err(format!(
+                "rule `{}`: mora_size={} doesn't match the {} moras of pron={}",
+                self.name,
+                feature.mora_size,
+                count_moras(&feature.pron),
+                feature.pron
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_target(target: &str) -> Result<(isize, Field), String> {
+    let (offset, field_name) = match target.split_once('.') {
+        Some((neighbour, field_name)) => {
+            let (sign, distance) = if let Some(distance) = neighbour.strip_prefix("prev") {
+                (-1, distance)
+            } else if let Some(distance) = neighbour.strip_prefix("next") {
+                (1, distance)
+            } else {
+                return Err(format!("unknown neighbour `{}`", neighbour));
+            };
+            let distance = if distance.is_empty() {
+                1
+            } else {
+                distance
+                    .parse::<isize>()
+                    .ok()
+                    .filter(|distance| *distance > 0)
+                    .ok_or_else(|| format!("unknown neighbour `{}`", neighbour))?
+            };
+            (sign * distance, field_name)
+        }
+        None => (0, target),
+    };
+    let field =
+        Field::from_name(field_name).ok_or_else(|| format!("unknown field `{}`", field_name))?;
+    Ok((offset, field))
+}
+
+fn parse_condition(condition: &str) -> Result<Condition, String> {
+    // 値や正規表現に `=` や `~` が含まれてもよいように、最初に現れた演算子で分ける
+    let position = condition
+        .find(['=', '~'])
+        .ok_or_else(|| format!("invalid condition `{}`", condition))?;
+    let (target, rest) = condition.split_at(position);
+    let matcher = match rest.split_at(1) {
+        ("~", pattern) => Matcher::Regex(
+            Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| format!("invalid regex `{}`: {}", pattern, e))?,
+        ),
+        (_, value) => Matcher::Equal(value.to_string()),
+    };
+    let (offset, field) = parse_target(target)?;
+    Ok(Condition {
+        offset,
+        field,
+        matcher,
+    })
+}
+
+fn parse_action(action: &str) -> Result<Action, String> {
+    let (field_name, value) = action
+        .split_once('=')
+        .ok_or_else(|| format!("invalid rewrite `{}`", action))?;
+    let field =
+        Field::from_name(field_name).ok_or_else(|| format!("unknown field `{}`", field_name))?;
+    if field.is_integer() && value.parse::<i32>().is_err() {
+        return Err(format!("`{}` requires an integer: `{}`", field_name, value));
+    }
+    if value.contains(',') {
+        return Err(format!("value must not contain `,`: `{}`", value));
+    }
+    Ok(Action {
+        field,
+        value: value.to_string(),
+    })
+}
+
+/// 適用された書き換え規則の記録
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FiredRule {
+    pub rule: String,
+    /// 書き換えた NJD ノードの、発音を付けた直後の素性の中での位置
+    pub index: usize,
+    pub before: NjdFeature,
+    pub after: NjdFeature,
+}
+
+impl fmt::Display for FiredRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: [{}] {} ({} -> {})",
+            self.rule, self.index, self.before.string, self.before.pron, self.after.pron
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RewriteRules {
+    rules: Vec<RewriteRule>,
+}
+
+impl RewriteRules {
+    pub fn new() -> RewriteRules {
+        RewriteRules::default()
+    }
+
+    pub fn parse(source: &str) -> Result<RewriteRules, String> {
+        let mut rules = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let line = match line.split_once('#') {
+                Some((line, _)) => line,
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            rules.push(RewriteRule::parse(line).map_err(|e| format!("line {}: {}", i + 1, e))?);
+        }
+        Ok(RewriteRules { rules })
+    }
+
+    pub fn load(path: &Path) -> Result<RewriteRules, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read rewrite rules: {}: {}", path.display(), e))?;
+        RewriteRules::parse(&source)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> &[RewriteRule] {
+        &self.rules
+    }
+
+    /// 先頭の形態素から順に、条件を満たすすべての規則を書かれた順に適用する
+    ///
+    /// 後の規則や後ろの形態素の条件は、それまでの書き換えを反映した素性に対して判定される。
+    /// 書き換えで発音とモーラ数が合わなくなった場合はエラーを返す。
+    pub fn apply(&self, features: &mut [NjdFeature]) -> Result<Vec<FiredRule>, String> {
+        let mut fired_rules = Vec::new();
+        for index in 0..features.len() {
+            for rule in self.rules.iter() {
+                if rule.is_match(features, index) {
+                    let before = features[index].clone();
+                    rule.apply(&mut features[index])?;
+                    fired_rules.push(FiredRule {
+                        rule: rule.name.clone(),
+                        index,
+                        before,
+                        after: features[index].clone(),
+                    });
+                }
+            }
+        }
+        Ok(fired_rules)
+    }
+}
+
+#[cfg(test)]
+mod rewrite_rule_tests {
+    use super::RewriteRules;
+    use openjtalk::NjdFeature;
+
+    fn features() -> Vec<NjdFeature> {
+        [
+            "学校,名詞,一般,*,*,*,*,学校,ガッコウ,ガッコー,0,4,C2,-1",
+            "に,助詞,格助詞,一般,*,*,*,に,ニ,ニ,0,1,名詞%F1,1",
+            "行っ,動詞,自立,*,*,五段・カ行促音便,連用タ接続,行く,イッ,イッ,0,2,*,0",
+            "た,助動詞,*,*,*,特殊・タ,基本形,た,タ,タ,0,1,動詞%F2@1/形容詞%F2@0,1",
+        ]
+        .iter()
+        .map(|feature| feature.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_apply_with_neighbours() {
+        let rules = RewriteRules::parse(
+            "# 学校に行った\n\
+             gakkou_itta: prev2.surface=学校 prev.surface=に surface=行っ => read=オコナッ pron=オコナッ mora_size=4\n\
+             never: surface~行.* next.pos=名詞 => pron=ダメ\n",
+        )
+        .unwrap();
+        let mut features = features();
+        let fired_rules = rules.apply(&mut features).unwrap();
+        assert_eq!(fired_rules.len(), 1);
+        assert_eq!(fired_rules[0].rule, "gakkou_itta");
+        assert_eq!(fired_rules[0].index, 2);
+        assert_eq!(features[2].pron, "オコナッ");
+        assert_eq!(features[2].mora_size, 4);
+    }
+
+    #[test]
+    fn test_parse_error_has_line_number() {
+        let err = RewriteRules::parse("ok: surface=は => pron=ワ\nbad: unknown=x => pron=ワ")
+            .unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+        assert!(RewriteRules::parse("bad: surface=は => acc=x").is_err());
+        assert!(RewriteRules::parse("bad: surface=は => pron=ワ mora_size=2").is_err());
+    }
+
+    #[test]
+    fn test_condition_with_operator_in_value() {
+        // 正規表現の中の `=` や、値の中の `~` で分けない
+        let rules = RewriteRules::parse(
+            "regex: read~(?:イッ|ニ) surface~[^=]+ => chain_flag=1\n\
+             tilde: pos=助詞~ => chain_flag=2",
+        )
+        .unwrap();
+        let mut features = features();
+        let fired_rules = rules.apply(&mut features).unwrap();
+        assert_eq!(fired_rules.len(), 2);
+        assert!(fired_rules
+            .iter()
+            .all(|fired_rule| fired_rule.rule == "regex"));
+    }
+
+    #[test]
+    fn test_mora_size_follows_pron() {
+        let mut rewritten = features();
+        RewriteRules::parse("wa: surface=に => pron=ワー")
+            .unwrap()
+            .apply(&mut rewritten)
+            .unwrap();
+        assert_eq!(rewritten[1].mora_size, 2);
+
+        let mut features = features();
+        let err = RewriteRules::parse("bad: surface=学校 => mora_size=3")
+            .unwrap()
+            .apply(&mut features)
+            .unwrap_err();
+        assert!(err.contains("bad"), "{}", err);
+    }
+}
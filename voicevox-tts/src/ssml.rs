@@ -0,0 +1,589 @@
+//! SSML の一部に対応した入力
+//!
+//! 対応する要素は `<speak>`、`<break>`、`<prosody>`、`<emphasis>`、`<say-as>`、`<sub>`、
+//! `<phoneme alphabet="x-voicevox-kana">`、`<voice>` と、区切りとして扱う `<p>`、`<s>`。
+//! それ以外の要素は中身のテキストだけを読み上げる。
+//!
+//! `<prosody>` と `<emphasis>` は [`AudioQueryModel`] の各倍率に、`<break>` はポーズのモーラに、
+//! `<voice>` は区間ごとの話者になる。
+
+use std::f32::consts::LN_2;
+
+use roxmltree::{Document, Node};
+
+use crate::{
+    audio::{AudioBuffer, TimedSpan},
+    audio_query::{INTONATION_SCALE_RANGE, SPEED_SCALE_RANGE, VOLUME_SCALE_RANGE},
+    model::AudioQueryModel,
+    tts::{TtsOptions, TtsResult},
+};
+
+/// `<prosody>` と `<emphasis>` による合成パラメータの変化
+///
+/// `speed_scale`、`volume_scale`、`intonation_scale` は倍率。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prosody {
+    pub speed_scale: f32,
+    /// モーラの音高（log F0）に加える値。F0 は `pitch_shift.exp()` 倍になる
+    pub pitch_shift: f32,
+    pub volume_scale: f32,
+    pub intonation_scale: f32,
+}
+
+impl Default for Prosody {
+    fn default() -> Self {
+        Prosody {
+            speed_scale: 1.0,
+            pitch_shift: 0.0,
+            volume_scale: 1.0,
+            intonation_scale: 1.0,
+        }
+    }
+}
+
+impl Prosody {
+    /// クエリの合成パラメータを変え、範囲に収める
+    ///
+    /// 音高は有声のモーラの `pitch` をずらす。合成では `pitch` に `2^pitch_scale` が掛かるので、
+    /// そのぶんを割ってから加え、`pitch_scale` によらず F0 が `pitch_shift.exp()` 倍になるようにする。
+    pub fn apply(&self, query: &mut AudioQueryModel) {
+        let clamp = |value: f32, range: std::ops::RangeInclusive<f32>| {
+            value.clamp(*range.start(), *range.end())
+        };
+        query.speed_scale = clamp(query.speed_scale * self.speed_scale, SPEED_SCALE_RANGE);
+        if self.pitch_shift != 0.0 {
+            let shift = self.pitch_shift / 2.0f32.powf(query.pitch_scale);
+            for accent_phrase in query.accent_phrases.iter_mut() {
+                for mora in accent_phrase.moras.iter_mut() {
+                    if mora.pitch > 0.0 {
+                        mora.pitch = (mora.pitch + shift).max(0.0);
+                    }
+                }
+            }
+        }
+        query.volume_scale = clamp(query.volume_scale * self.volume_scale, VOLUME_SCALE_RANGE);
+        query.intonation_scale = clamp(
+            query.intonation_scale * self.intonation_scale,
+            INTONATION_SCALE_RANGE,
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SsmlContent {
+    /// 日本語処理部で解析するテキスト
+    Text(String),
+    /// AquesTalk 風記法の読み
+    Kana(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SsmlItem {
+    Speech {
+        content: SsmlContent,
+        /// `<voice name>` の話者名。省略した場合は呼び出し側の話者
+        voice: Option<String>,
+        prosody: Prosody,
+    },
+    /// 無音（秒）
+    Break(f32),
+}
+
+#[derive(Clone, Default)]
+struct Context {
+    voice: Option<String>,
+    prosody: Prosody,
+}
+
+fn parse_number(value: &str, suffix: &str) -> Option<f32> {
+    value.strip_suffix(suffix)?.trim().parse().ok()
+}
+
+/// `500ms` や `1.5s` を秒にする
+pub(crate) fn parse_time(value: &str) -> Result<f32, String> {
+    let value = value.trim();
+    let seconds = parse_number(value, "ms")
+        .map(|ms| ms / 1000.0)
+        .or_else(|| parse_number(value, "s"))
+        .ok_or_else(|| format!("invalid time `{}`", value))?;
+    if seconds < 0.0 {
+        return Err(format!("time must not be negative, but got `{}`", value));
+    }
+    Ok(seconds)
+}
+
+fn parse_break(node: Node) -> Result<f32, String> {
+    if let Some(time) = node.attribute("time") {
+        return parse_time(time);
+    }
+    Ok(match node.attribute("strength").unwrap_or("medium") {
+        "none" => 0.0,
+        "x-weak" => 0.1,
+        "weak" => 0.2,
+        "medium" => 0.4,
+        "strong" => 0.7,
+        "x-strong" => 1.0,
+        strength => return Err(format!("invalid break strength `{}`", strength)),
+    })
+}
+
+/// `rate` を話速の倍率にする
+fn parse_rate(value: &str) -> Result<f32, String> {
+    Ok(match value {
+        "x-slow" => 0.6,
+        "slow" => 0.8,
+        "medium" | "default" => 1.0,
+        "fast" => 1.25,
+        "x-fast" => 1.5,
+        _ => parse_number(value, "%")
+            .map(|percent| percent / 100.0)
+            .or_else(|| value.parse().ok())
+            .ok_or_else(|| format!("invalid prosody rate `{}`", value))?,
+    })
+}
+
+/// `pitch` を log F0 に加える値（F0 の比の自然対数）にする
+fn parse_pitch(value: &str) -> Result<f32, String> {
+    Ok(match value {
+        "x-low" => -0.1 * LN_2,
+        "low" => -0.05 * LN_2,
+        "medium" | "default" => 0.0,
+        "high" => 0.05 * LN_2,
+        "x-high" => 0.1 * LN_2,
+        _ => {
+            if let Some(semitones) = parse_number(value, "st") {
+                semitones / 12.0 * LN_2
+            } else if let Some(percent) = parse_number(value, "%") {
+                (1.0 + percent / 100.0).max(f32::MIN_POSITIVE).ln()
+            } else {
+                return Err(format!("invalid prosody pitch `{}`", value));
+            }
+        }
+    })
+}
+
+/// `volume` を音量の倍率にする
+fn parse_volume(value: &str) -> Result<f32, String> {
+    Ok(match value {
+        "silent" => 0.0,
+        "x-soft" => 0.25,
+        "soft" => 0.5,
+        "medium" | "default" => 1.0,
+        "loud" => 1.5,
+        "x-loud" => 2.0,
+        _ => {
+            if let Some(db) = parse_number(value, "dB") {
+                10.0f32.powf(db / 20.0)
+            } else if let Some(percent) = parse_number(value, "%") {
+                (1.0 + percent / 100.0).max(0.0)
+            } else {
+                return Err(format!("invalid prosody volume `{}`", value));
+            }
+        }
+    })
+}
+
+fn emphasis_intonation(level: &str) -> Result<f32, String> {
+    Ok(match level {
+        "strong" => 1.5,
+        "moderate" => 1.25,
+        "none" => 1.0,
+        "reduced" => 0.75,
+        _ => return Err(format!("invalid emphasis level `{}`", level)),
+    })
+}
+
+const DIGIT_READINGS: [&str; 10] = [
+    "ゼロ",
+    "イチ",
+    "ニー",
+    "サン",
+    "ヨン",
+    "ゴー",
+    "ロク",
+    "ナナ",
+    "ハチ",
+    "キュー",
+];
+
+const LETTER_READINGS: [&str; 26] = [
+    "エー",
+    "ビー",
+    "シー",
+    "ディー",
+    "イー",
+    "エフ",
+    "ジー",
+    "エイチ",
+    "アイ",
+    "ジェー",
+    "ケー",
+    "エル",
+    "エム",
+    "エヌ",
+    "オー",
+    "ピー",
+    "キュー",
+    "アール",
+    "エス",
+    "ティー",
+    "ユー",
+    "ブイ",
+    "ダブリュー",
+    "エックス",
+    "ワイ",
+    "ゼット",
+];
+
+/// 英数字を 1 文字ずつの読みにする
+fn spell_out(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| {
+            // 全角の英数字は半角にしてから読む
+            let c = match c {
+                '０'..='９' | 'Ａ'..='Ｚ' | 'ａ'..='ｚ' => {
+                    char::from_u32(c as u32 - 0xfee0).unwrap()
+                }
+                _ => c,
+            };
+            if c.is_whitespace() {
+                None
+            } else if let Some(digit) = c.to_digit(10) {
+                Some(DIGIT_READINGS[digit as usize].to_string())
+            } else if c.is_ascii_alphabetic() {
+                Some(LETTER_READINGS[(c.to_ascii_uppercase() as u8 - b'A') as usize].to_string())
+            } else {
+                Some(c.to_string())
+            }
+        })
+        .collect()
+}
+
+/// `2024-01-05` のような日付を `2024年1月5日` にする
+///
+/// `format` は `ymd`、`ym`、`md`、`y`、`m`、`d` の組み合わせで、省略した場合は要素の数から決める。
+fn format_date(text: &str, format: Option<&str>) -> Result<String, String> {
+    let parts = text
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u32>().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let format = match format {
+        Some(format) => format,
+        None => match parts.len() {
+            3 => "ymd",
+            2 => "md",
+            1 => "d",
+            _ => return Err(format!("invalid date `{}`", text)),
+        },
+    };
+    if format.len() != parts.len() {
+        return Err(format!(
+            "date `{}` does not match format `{}`",
+            text, format
+        ));
+    }
+    format
+        .chars()
+        .zip(parts)
+        .map(|(field, value)| match field {
+            'y' => Ok(format!("{}年", value)),
+            'm' => Ok(format!("{}月", value)),
+            'd' => Ok(format!("{}日", value)),
+            _ => Err(format!("invalid date format `{}`", format)),
+        })
+        .collect()
+}
+
+fn say_as(node: Node, text: &str) -> Result<String, String> {
+    match node.attribute("interpret-as") {
+        Some("characters") | Some("spell-out") => Ok(spell_out(text)),
+        Some("cardinal") | Some("number") => Ok(text
+            .chars()
+            .filter(|c| !matches!(c, ',' | '，') && !c.is_whitespace())
+            .collect()),
+        Some("date") => format_date(text, node.attribute("format")),
+        Some(other) => Err(format!("unsupported interpret-as `{}`", other)),
+        None => Err("say-as requires interpret-as".to_string()),
+    }
+}
+
+fn text_content(node: Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect()
+}
+
+fn push_text(items: &mut Vec<SsmlItem>, context: &Context, content: SsmlContent) {
+    let is_empty = match &content {
+        SsmlContent::Text(text) | SsmlContent::Kana(text) => text.trim().is_empty(),
+    };
+    if !is_empty {
+        items.push(SsmlItem::Speech {
+            content,
+            voice: context.voice.clone(),
+            prosody: context.prosody,
+        });
+    }
+}
+
+fn walk(node: Node, context: &Context, items: &mut Vec<SsmlItem>) -> Result<(), String> {
+    for child in node.children() {
+        if child.is_text() {
+            let text = child.text().unwrap_or_default();
+            push_text(items, context, SsmlContent::Text(text.trim().to_string()));
+            continue;
+        }
+        if !child.is_element() {
+            continue;
+        }
+        let mut context = context.clone();
+        match child.tag_name().name() {
+            "break" => {
+                items.push(SsmlItem::Break(parse_break(child)?));
+                continue;
+            }
+            "prosody" => {
+                if let Some(rate) = child.attribute("rate") {
+                    context.prosody.speed_scale *= parse_rate(rate)?;
+                }
+                if let Some(pitch) = child.attribute("pitch") {
+                    context.prosody.pitch_shift += parse_pitch(pitch)?;
+                }
+                if let Some(volume) = child.attribute("volume") {
+                    context.prosody.volume_scale *= parse_volume(volume)?;
+                }
+            }
+            "emphasis" => {
+                context.prosody.intonation_scale *=
+                    emphasis_intonation(child.attribute("level").unwrap_or("moderate"))?;
+            }
+            "voice" => {
+                context.voice = Some(
+                    child
+                        .attribute("name")
+                        .ok_or("voice requires name")?
+                        .to_string(),
+                );
+            }
+            "say-as" => {
+                let text = say_as(child, &text_content(child))?;
+                push_text(items, &context, SsmlContent::Text(text));
+                continue;
+            }
+            "sub" => {
+                let alias = child.attribute("alias").ok_or("sub requires alias")?;
+                push_text(items, &context, SsmlContent::Text(alias.to_string()));
+                continue;
+            }
+            "phoneme" => {
+                match child.attribute("alphabet") {
+                    Some("x-voicevox-kana") => {}
+                    alphabet => {
+                        return Err(format!(
+                            "unsupported phoneme alphabet `{}`",
+                            alphabet.unwrap_or_default()
+                        ))
+                    }
+                }
+                let ph = child.attribute("ph").ok_or("phoneme requires ph")?;
+                push_text(items, &context, SsmlContent::Kana(ph.to_string()));
+                continue;
+            }
+            _ => {}
+        }
+        walk(child, &context, items)?;
+        match child.tag_name().name() {
+            "s" => items.push(SsmlItem::Break(0.4)),
+            "p" => items.push(SsmlItem::Break(0.7)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// SSML を読み上げる内容と無音の列にする
+pub fn parse_ssml(ssml: &str) -> Result<Vec<SsmlItem>, String> {
+    let document = Document::parse(ssml).map_err(|e| format!("invalid SSML: {}", e))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "speak" {
+        return Err(format!(
+            "root element must be speak, but got {}",
+            root.tag_name().name()
+        ));
+    }
+    let mut items = Vec::new();
+    walk(root, &Context::default(), &mut items)?;
+    Ok(items)
+}
+
+/// 音声の中での区間の位置（秒）
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsmlSpan {
+    /// 区間の読み（AquesTalk 風記法）
+    pub kana: String,
+    pub speaker_id: i64,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TimedSpan for SsmlSpan {
+    fn start(&self) -> f32 {
+        self.start
+    }
+
+    fn end(&self) -> f32 {
+        self.end
+    }
+
+    fn set_time(&mut self, start: f32, end: f32) {
+        (self.start, self.end) = (start, end);
+    }
+}
+
+/// SSML の音声合成の結果
+#[derive(Debug, Clone)]
+pub struct SsmlResult {
+    /// 区間はまとめて合成した部分ごとの位置
+    pub audio: AudioBuffer<SsmlSpan>,
+}
+
+impl SsmlResult {
+    pub(crate) fn new(options: &TtsOptions) -> SsmlResult {
+        let channels = if options.output_stereo { 2 } else { 1 };
+        SsmlResult {
+            audio: AudioBuffer::new(Vec::new(), options.output_sampling_rate, channels),
+        }
+    }
+
+    pub(crate) fn push(&mut self, speaker_id: i64, result: TtsResult) {
+        let kana = result.audio_query.kana;
+        self.audio
+            .push(result.audio.samples, result.audio.timings, |start, end| {
+                SsmlSpan {
+                    kana,
+                    speaker_id,
+                    start,
+                    end,
+                }
+            });
+    }
+
+    /// まとめて合成した部分ごとの位置
+    pub fn spans(&self) -> &[SsmlSpan] {
+        &self.audio.spans
+    }
+}
+
+#[cfg(test)]
+mod ssml_tests {
+    use std::f32::consts::LN_2;
+
+    use super::{parse_pitch, parse_ssml, Prosody, SsmlContent, SsmlItem};
+    use crate::{
+        direct_input::accent_phrases_from_kana, model::AudioQueryModel,
+        synthesis_engine::SynthesisEngine,
+    };
+
+    fn text(text: &str, voice: Option<&str>, prosody: Prosody) -> SsmlItem {
+        SsmlItem::Speech {
+            content: SsmlContent::Text(text.to_string()),
+            voice: voice.map(|voice| voice.to_string()),
+            prosody,
+        }
+    }
+
+    #[test]
+    fn test_parse_ssml() {
+        let items = parse_ssml(
+            r#"<speak xmlns="http://www.w3.org/2001/10/synthesis">
+                こんにちは<break time="500ms"/>
+                <prosody rate="150%" pitch="+12st" volume="+6dB">速く</prosody>
+                <voice name="ずんだもん"><emphasis level="strong">強く</emphasis></voice>
+                <say-as interpret-as="characters">AB1</say-as>
+                <say-as interpret-as="cardinal">1,234</say-as>
+                <say-as interpret-as="date">2024-01-05</say-as>
+                <sub alias="ダブリューダブリューダブリュー">WWW</sub>
+                <phoneme alphabet="x-voicevox-kana" ph="コンニチワ'">こんにちは</phoneme>
+                <break strength="strong"/>
+            </speak>"#,
+        )
+        .unwrap();
+        let fast = Prosody {
+            speed_scale: 1.5,
+            pitch_shift: LN_2,
+            volume_scale: 10.0f32.powf(0.3),
+            intonation_scale: 1.0,
+        };
+        let strong = Prosody {
+            intonation_scale: 1.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            items,
+            [
+                text("こんにちは", None, Prosody::default()),
+                SsmlItem::Break(0.5),
+                text("速く", None, fast),
+                text("強く", Some("ずんだもん"), strong),
+                text("エービーイチ", None, Prosody::default()),
+                text("1234", None, Prosody::default()),
+                text("2024年1月5日", None, Prosody::default()),
+                text("ダブリューダブリューダブリュー", None, Prosody::default()),
+                SsmlItem::Speech {
+                    content: SsmlContent::Kana("コンニチワ'".to_string()),
+                    voice: None,
+                    prosody: Prosody::default(),
+                },
+                SsmlItem::Break(0.7),
+            ]
+        );
+
+        assert!(parse_ssml("<p>こんにちは</p>").is_err());
+        assert!(parse_ssml(r#"<speak><break time="fast"/></speak>"#).is_err());
+        assert!(
+            parse_ssml(r#"<speak><phoneme alphabet="ipa" ph="a">あ</phoneme></speak>"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_pitch_changes_f0_ratio() {
+        let mut accent_phrases = accent_phrases_from_kana("コンニチワ、キョ'ーワ").unwrap();
+        for (i, mora) in accent_phrases
+            .iter_mut()
+            .flat_map(|accent_phrase| accent_phrase.moras.iter_mut())
+            .enumerate()
+        {
+            mora.vowel_length = 0.1;
+            mora.pitch = if i == 3 { 0.0 } else { 5.0 + 0.1 * i as f32 };
+        }
+        let query = AudioQueryModel::builder(accent_phrases)
+            .pitch_scale(0.1)
+            .intonation_scale(1.2)
+            .build()
+            .unwrap();
+        let f0 = |pitch: &str| {
+            let mut query = query.clone();
+            Prosody {
+                pitch_shift: parse_pitch(pitch).unwrap(),
+                ..Default::default()
+            }
+            .apply(&mut query);
+            SynthesisEngine::frame_features(query, false).unwrap().f0
+        };
+
+        let base = f0("default");
+        for (pitch, ratio) in [("+12st", 2.0), ("-50%", 0.5), ("x-high", 2f32.powf(0.1))] {
+            for (shifted, base) in f0(pitch).iter().zip(base.iter()) {
+                if *base == 0.0 {
+                    assert_eq!(*shifted, 0.0);
+                } else {
+                    // f0 は log F0 なので、差が F0 の比の対数になる
+                    let actual = (shifted - base).exp();
+                    assert!((actual - ratio).abs() < 1e-3, "{}: {}", pitch, actual);
+                }
+            }
+        }
+    }
+}
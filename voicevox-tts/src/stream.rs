@@ -0,0 +1,230 @@
+//! 息継ぎごとに分けた音声合成
+//!
+//! 文全体の音素長と音高を求めたあと、ポーズの中央で区切った区間ごとに音声を生成する。
+//! 区間の前後には少しだけ隣の区間の特徴量を与え、つなぎ目で音声が途切れないようにする。
+
+use std::{ops::Range, sync::mpsc::Sender};
+
+use crate::{
+    acoustic_feature_extractor::OjtPhoneme,
+    model::AudioQueryModel,
+    resampler::Resampler,
+    synthesis_engine::{FrameFeatures, SynthesisEngine, DEFAULT_SAMPLING_RATE},
+    tts::Timings,
+};
+
+/// 音声合成モデルの 1 フレームのサンプル数
+const FRAME_SIZE: usize = 256;
+/// 区間の前後に与える文脈のフレーム数（約 0.2 秒）
+const CONTEXT_FRAMES: usize = 20;
+
+/// 息継ぎごとの音声を順に返すイテレータ
+///
+/// 各要素は音量を反映した音声で、ステレオの場合は左右のサンプルが交互に並ぶ。
+pub struct SynthesisStream<'a> {
+    engine: &'a SynthesisEngine,
+    speaker_id: i64,
+    features: FrameFeatures,
+    segments: Vec<Range<usize>>,
+    next_segment: usize,
+    volume_scale: f32,
+    sampling_rate: u32,
+    channels: u16,
+    resampler: Option<Resampler>,
+}
+
+/// 文中のポーズの中央のフレームで区切った区間
+fn split_at_pauses(phonemes: &[OjtPhoneme], frames: &[usize]) -> Vec<Range<usize>> {
+    let mut segments = Vec::new();
+    let mut segment_start = 0;
+    let mut frame = 0;
+    for (i, (phoneme, length)) in phonemes.iter().zip(frames).enumerate() {
+        // 最初と最後の無音では区切らない
+        let is_inner = i != 0 && i + 1 != phonemes.len();
+        if is_inner && phoneme.phoneme == "pau" {
+            let boundary = frame + length / 2;
+            if boundary > segment_start {
+                segments.push(segment_start..boundary);
+                segment_start = boundary;
+            }
+        }
+        frame += length;
+    }
+    if frame > segment_start {
+        segments.push(segment_start..frame);
+    }
+    segments
+}
+
+/// 区間の前後に文脈のフレームを足した範囲
+fn context_range(segment: &Range<usize>, total_frames: usize) -> Range<usize> {
+    segment.start.saturating_sub(CONTEXT_FRAMES)..(segment.end + CONTEXT_FRAMES).min(total_frames)
+}
+
+/// 文脈を含めて生成した音声から区間の部分を切り出す
+///
+/// サンプリングレートは文脈ごと変換してから切り出す。区間の境界は音声全体で揃えるので、
+/// 区間ごとの長さの和は音声全体を一度に変換した長さと一致する。
+fn trim_context(
+    wave: &[f32],
+    context: &Range<usize>,
+    segment: &Range<usize>,
+    resampler: Option<&Resampler>,
+) -> Vec<f32> {
+    match resampler {
+        Some(resampler) => resampler.process_part(
+            wave,
+            1,
+            context.start * FRAME_SIZE,
+            resampler.output_position(segment.start * FRAME_SIZE)
+                ..resampler.output_position(segment.end * FRAME_SIZE),
+        ),
+        None => {
+            let offset = (segment.start - context.start) * FRAME_SIZE;
+            wave[offset..offset + segment.len() * FRAME_SIZE].to_vec()
+        }
+    }
+}
+
+impl<'a> SynthesisStream<'a> {
+    pub(crate) fn new(
+        engine: &'a SynthesisEngine,
+        query: AudioQueryModel,
+        speaker_id: i64,
+        enable_interrogative_upspeak: bool,
+    ) -> Result<SynthesisStream<'a>, String> {
+        let volume_scale = query.volume_scale;
+        let sampling_rate = query.output_sampling_rate;
+        let channels = if query.output_stereo { 2 } else { 1 };
+        let features = SynthesisEngine::frame_features(query, enable_interrogative_upspeak)?;
+        let segments = split_at_pauses(&features.phoneme_data_list, &features.phoneme_frames);
+        let resampler = if sampling_rate == DEFAULT_SAMPLING_RATE {
+            None
+        } else {
+            Some(Resampler::new(DEFAULT_SAMPLING_RATE, sampling_rate)?)
+        };
+        Ok(SynthesisStream {
+            engine,
+            speaker_id,
+            features,
+            segments,
+            next_segment: 0,
+            volume_scale,
+            sampling_rate,
+            channels,
+            resampler,
+        })
+    }
+
+    pub fn sampling_rate(&self) -> u32 {
+        self.sampling_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// 音声全体での各音素・各モーラの時刻
+    pub fn timings(&self) -> &Timings {
+        &self.features.timings
+    }
+
+    /// 区間の数
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    fn synthesis_segment(&self, segment: &Range<usize>) -> Result<Vec<f32>, String> {
+        let context = context_range(segment, self.features.f0.len());
+        let num_phoneme = OjtPhoneme::num_phoneme();
+        let mut f0 = self.features.f0[context.clone()].to_vec();
+        let mut phoneme =
+            self.features.phoneme[context.start * num_phoneme..context.end * num_phoneme].to_vec();
+        let wave = self.engine.decode(&mut f0, &mut phoneme, self.speaker_id)?;
+        let wave = trim_context(&wave, &context, segment, self.resampler.as_ref());
+        Ok(wave
+            .iter()
+            .flat_map(|value| {
+                std::iter::repeat_n(value * self.volume_scale, self.channels as usize)
+            })
+            .collect())
+    }
+
+    /// 残りの音声を順に `sender` に送る
+    ///
+    /// 受け取る側が切断した場合はそこで止める。
+    pub fn send_to(self, sender: &Sender<Result<Vec<f32>, String>>) {
+        for chunk in self {
+            let is_err = chunk.is_err();
+            if sender.send(chunk).is_err() || is_err {
+                break;
+            }
+        }
+    }
+}
+
+impl Iterator for SynthesisStream<'_> {
+    type Item = Result<Vec<f32>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let segment = self.segments.get(self.next_segment)?.clone();
+        self.next_segment += 1;
+        Some(self.synthesis_segment(&segment))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.segments.len() - self.next_segment;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use super::{context_range, split_at_pauses, trim_context, FRAME_SIZE};
+    use crate::{
+        resampler::{resample, Resampler},
+        synthesis_engine::{to_phoneme_data_list, DEFAULT_SAMPLING_RATE},
+    };
+
+    #[test]
+    fn test_trim_context_matches_whole_resampling() {
+        let segments = [0..28, 28..49, 49..68];
+        let total_frames = 68usize;
+        let wave = (0..total_frames * FRAME_SIZE)
+            .map(|i| (i as f32 * 0.01).sin() * 0.5)
+            .collect::<Vec<_>>();
+        let whole = resample(&wave, DEFAULT_SAMPLING_RATE, 44100).unwrap();
+
+        let resampler = Resampler::new(DEFAULT_SAMPLING_RATE, 44100).unwrap();
+        let streamed = segments
+            .iter()
+            .flat_map(|segment| {
+                let context = context_range(segment, total_frames);
+                let part = &wave[context.start * FRAME_SIZE..context.end * FRAME_SIZE];
+                trim_context(part, &context, segment, Some(&resampler))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(streamed.len(), whole.len());
+        let error = streamed
+            .iter()
+            .zip(whole.iter())
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 1e-5, "{}", error);
+    }
+
+    #[test]
+    fn test_split_at_pauses() {
+        let phonemes = to_phoneme_data_list(
+            ["pau", "k", "a", "pau", "N", "pau", "a", "pau"]
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+        );
+        let segments = split_at_pauses(&phonemes, &[10, 3, 5, 20, 7, 9, 4, 10]);
+        assert_eq!(segments, [0..28, 28..49, 49..68]);
+        let segments = split_at_pauses(&phonemes[..3], &[10, 3, 5]);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0], 0..18);
+    }
+}
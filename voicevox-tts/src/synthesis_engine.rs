@@ -1,14 +1,19 @@
 use crate::{
+    accent_phrase_edit::AccentPhraseEdit,
     acoustic_feature_extractor::OjtPhoneme,
-    full_context_label::extract_fullcontext,
+    audio_query::check_accent_phrases,
+    encoder::wav::SampleFormat,
+    frontend::{openjtalk::OpenJTalkFrontend, Frontend, Word},
+    kana_parser::parse_kana,
     model::{AccentPhraseModel, AudioQueryModel, MoraModel},
     mora_list::mora2text,
+    rewrite_rule::{FiredRule, RewriteRules},
+    stream::SynthesisStream,
+    tts::{Timings, TtsResult},
 };
-use openjtalk::OpenJTalk;
+use openjtalk::{NjdFeature, OpenJTalk};
 use voicevox_core::VVCore;
 
-use std::io::{Cursor, Seek, SeekFrom, Write};
-
 pub const UNVOICED_MORA_LIST: &[&str] = &["A", "I", "U", "E", "O", "cl", "pau"];
 
 pub const MORA_PHONEME_LIST: &[&str] = &[
@@ -85,20 +90,23 @@ pub fn adjust_interrogative_accent_phrases(
 }
 
 fn adjust_interrogative_moras(accent_phrase: AccentPhraseModel) -> Vec<MoraModel> {
-    let moras = accent_phrase.moras;
-    if accent_phrase.is_interrogative && !moras.is_empty() {
-        let last_mora = moras.last().unwrap().clone();
-        let last_mora_pitch = last_mora.pitch;
-        if last_mora_pitch != 0.0 {
-            let mut new_moras = moras.clone();
-            let interrogative_mora = make_interrogative_mora(last_mora);
-            new_moras.push(interrogative_mora);
-            return new_moras;
-        }
+    let mut moras = accent_phrase.moras.clone();
+    if let Some(interrogative_mora) = interrogative_mora(&accent_phrase) {
+        moras.push(interrogative_mora);
     }
     moras
 }
 
+/// 疑問文の末尾の音高を上げるために、アクセント句の後ろに足すモーラ
+pub(crate) fn interrogative_mora(accent_phrase: &AccentPhraseModel) -> Option<MoraModel> {
+    let last_mora = accent_phrase.moras.last()?;
+    if accent_phrase.is_interrogative && last_mora.pitch != 0.0 {
+        Some(make_interrogative_mora(last_mora.clone()))
+    } else {
+        None
+    }
+}
+
 fn make_interrogative_mora(last_mora: MoraModel) -> MoraModel {
     let fix_vowel_length = 0.15;
     let adjust_pitch = 0.3;
@@ -120,14 +128,63 @@ fn make_interrogative_mora(last_mora: MoraModel) -> MoraModel {
 
 pub const DEFAULT_SAMPLING_RATE: u32 = 24000;
 
+/// 音声合成モデルの 1 フレームは 256 サンプルに相当する
+pub const FRAME_RATE: f32 = DEFAULT_SAMPLING_RATE as f32 / 256.0;
+
+/// 音声合成モデルに与えるフレームごとの特徴量
+pub(crate) struct FrameFeatures {
+    pub f0: Vec<f32>,
+    /// フレームごとの音素の one-hot ベクトルをつなげたもの
+    pub phoneme: Vec<f32>,
+    /// 前後の無音を含む音素の列と、各音素のフレーム数
+    pub phoneme_data_list: Vec<OjtPhoneme>,
+    pub phoneme_frames: Vec<usize>,
+    pub timings: Timings,
+}
+
 pub struct SynthesisEngine {
-    openjtalk: OpenJTalk,
+    openjtalk_frontend: OpenJTalkFrontend,
+    frontend: Option<Box<dyn Frontend>>,
     core: VVCore,
 }
 
 impl SynthesisEngine {
     pub fn new(openjtalk: OpenJTalk, core: VVCore) -> SynthesisEngine {
-        SynthesisEngine { openjtalk, core }
+        SynthesisEngine {
+            openjtalk_frontend: OpenJTalkFrontend::new(openjtalk),
+            frontend: None,
+            core,
+        }
+    }
+
+    /// [`create_accent_phrases`](Self::create_accent_phrases) で使う日本語処理部を
+    /// OpenJTalk から差し替える
+    ///
+    /// 書き換え規則は OpenJTalk の素性に対するものなので、規則を設定したまま
+    /// 差し替えた日本語処理部でテキストを解析するとエラーになる。
+    pub fn set_frontend(&mut self, frontend: Box<dyn Frontend>) {
+        self.frontend = Some(frontend);
+    }
+
+    /// 日本語処理部を OpenJTalk に戻す
+    pub fn reset_frontend(&mut self) {
+        self.frontend = None;
+    }
+
+    /// [`create_accent_phrases`](Self::create_accent_phrases) で
+    /// 発音を付けた直後の NJD の素性へ適用する書き換え規則を設定する
+    ///
+    /// [`set_frontend`](Self::set_frontend) で日本語処理部を差し替えている間は使えない。
+    pub fn set_rewrite_rules(&mut self, rewrite_rules: RewriteRules) {
+        self.openjtalk_frontend.set_rewrite_rules(rewrite_rules);
+    }
+
+    /// テキストを解析して書き換え規則を適用し、適用後の素性と適用された規則を返す
+    pub fn apply_rewrite_rules(
+        &self,
+        text: &str,
+    ) -> Result<(Vec<NjdFeature>, Vec<FiredRule>), String> {
+        self.openjtalk_frontend.apply_rewrite_rules(text)
     }
 
     pub fn create_accent_phrases(
@@ -135,87 +192,79 @@ impl SynthesisEngine {
         text: String,
         speaker_id: i64,
     ) -> Result<Vec<AccentPhraseModel>, String> {
+        let accent_phrases = self.analyze_text(&text)?;
+        if accent_phrases.is_empty() {
+            return Ok(accent_phrases);
+        }
+        self.replace_mora_data(accent_phrases, speaker_id)
+    }
+
+    /// 日本語処理部でテキストをアクセント句にする。音素長と音高は予測しない
+    pub fn analyze_text(&self, text: &str) -> Result<Vec<AccentPhraseModel>, String> {
         if text.is_empty() {
             return Ok(Vec::new());
         }
+        self.frontend()?.create_accent_phrases(text)
+    }
+
+    /// テキストからアクセント句を作り、同じ解析で分けた単語と一緒に返す
+    pub fn create_accent_phrases_with_words(
+        &self,
+        text: &str,
+        speaker_id: i64,
+    ) -> Result<(Vec<AccentPhraseModel>, Vec<Word>), String> {
+        if text.is_empty() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+        let (accent_phrases, words) = self.frontend()?.analyze(text)?;
+        if accent_phrases.is_empty() {
+            return Ok((accent_phrases, words));
+        }
+        Ok((self.replace_mora_data(accent_phrases, speaker_id)?, words))
+    }
 
-        let utterance = extract_fullcontext(self.openjtalk, text);
-        if utterance.is_err() || utterance.as_ref().unwrap().breath_groups.is_empty() {
+    /// 日本語処理部でテキストを単語に分ける
+    pub fn words(&self, text: &str) -> Result<Vec<Word>, String> {
+        if text.is_empty() {
             return Ok(Vec::new());
         }
-        let utterance = utterance.unwrap();
+        self.frontend()?.words(text)
+    }
 
-        let accent_phrases: Vec<AccentPhraseModel> = utterance
-            .breath_groups
-            .iter()
-            .enumerate()
-            .fold(Vec::new(), |mut acc_vec, (i, breath_group)| {
-                acc_vec.append(
-                    &mut breath_group
-                        .accent_phrases
-                        .iter()
-                        .enumerate()
-                        .map(|(j, accent_phrase)| {
-                            let moras = accent_phrase
-                                .moras
-                                .iter()
-                                .map(|mora| {
-                                    let mut moras_text = mora
-                                        .phonemes()
-                                        .iter()
-                                        .map(|phoneme| phoneme.phoneme())
-                                        .collect::<Vec<_>>()
-                                        .join("");
-                                    moras_text = moras_text.to_lowercase();
-                                    if moras_text == "n" {
-                                        moras_text = String::from("N");
-                                    }
-                                    let (consonant, consonant_length) = if mora.consonant.is_some()
-                                    {
-                                        (
-                                            Some(mora.consonant.as_ref().unwrap().phoneme()),
-                                            Some(0.0),
-                                        )
-                                    } else {
-                                        (None, None)
-                                    };
-                                    MoraModel {
-                                        text: moras_text,
-                                        consonant,
-                                        consonant_length,
-                                        vowel: mora.vowel.phoneme(),
-                                        vowel_length: 0.0,
-                                        pitch: 0.0,
-                                    }
-                                })
-                                .collect();
-
-                            let pause_mora = if i != &utterance.breath_groups.len() - 1
-                                && j == breath_group.accent_phrases.len() - 1
-                            {
-                                Some(MoraModel {
-                                    text: "、".to_string(),
-                                    consonant: None,
-                                    consonant_length: None,
-                                    vowel: "pau".to_string(),
-                                    vowel_length: 0.0,
-                                    pitch: 0.0,
-                                })
-                            } else {
-                                None
-                            };
-                            AccentPhraseModel {
-                                moras,
-                                accent: accent_phrase.accent,
-                                pause_mora,
-                                is_interrogative: accent_phrase.is_interrogative,
-                            }
-                        })
-                        .collect(),
-                );
-                acc_vec
-            });
+    fn frontend(&self) -> Result<&dyn Frontend, String> {
+        match self.frontend {
+            Some(_) if self.openjtalk_frontend.has_rewrite_rules() => {
+                Err("rewrite rules can't be used with a custom frontend".to_string())
+            }
+            Some(ref frontend) => Ok(frontend.as_ref()),
+            None => Ok(&self.openjtalk_frontend),
+        }
+    }
 
+    /// [`OpenJTalk::run_frontend_nbest`] などで得た NJD の素性からアクセント句を作る
+    ///
+    /// [`OpenJTalk::run_frontend_nbest`]: openjtalk::OpenJTalk::run_frontend_nbest
+    pub fn create_accent_phrases_from_features(
+        &self,
+        features: &[NjdFeature],
+        speaker_id: i64,
+    ) -> Result<Vec<AccentPhraseModel>, String> {
+        let accent_phrases = self
+            .openjtalk_frontend
+            .create_accent_phrases_from_features(features)?;
+        if accent_phrases.is_empty() {
+            return Ok(accent_phrases);
+        }
+        self.replace_mora_data(accent_phrases, speaker_id)
+    }
+
+    /// AquesTalk 風記法のテキストからアクセント句を作る
+    pub fn create_accent_phrases_from_kana(
+        &self,
+        kana: &str,
+        speaker_id: i64,
+    ) -> Result<Vec<AccentPhraseModel>, String> {
+        let accent_phrases = parse_kana(kana).map_err(|e| e.to_string())?;
         self.replace_mora_data(accent_phrases, speaker_id)
     }
 
@@ -230,11 +279,44 @@ impl SynthesisEngine {
         )
     }
 
+    /// `targets` に番号を指定したアクセント句だけ、音素長と音高を予測し直す
+    ///
+    /// 予測は前後の文脈を含めて全体で行うが、それ以外のアクセント句の値は変えない。
+    pub fn replace_mora_data_of(
+        &self,
+        accent_phrases: Vec<AccentPhraseModel>,
+        targets: &[usize],
+        speaker_id: i64,
+    ) -> Result<Vec<AccentPhraseModel>, String> {
+        if targets.is_empty() {
+            return Ok(accent_phrases);
+        }
+        let predicted = self.replace_mora_data(accent_phrases.clone(), speaker_id)?;
+        let mut accent_phrases = accent_phrases;
+        for &i in targets {
+            accent_phrases[i] = predicted[i].clone();
+        }
+        Ok(accent_phrases)
+    }
+
+    /// アクセント句を編集し、編集したアクセント句だけ音素長と音高を予測し直す
+    pub fn edit_accent_phrases(
+        &self,
+        accent_phrases: Vec<AccentPhraseModel>,
+        edit: &AccentPhraseEdit,
+        speaker_id: i64,
+    ) -> Result<Vec<AccentPhraseModel>, String> {
+        let mut accent_phrases = accent_phrases;
+        let targets = edit.apply(&mut accent_phrases)?;
+        self.replace_mora_data_of(accent_phrases, &targets, speaker_id)
+    }
+
     pub fn replace_phoneme_length(
         &self,
         accent_phrases: Vec<AccentPhraseModel>,
         speaker_id: i64,
     ) -> Result<Vec<AccentPhraseModel>, String> {
+        check_accent_phrases(&accent_phrases)?;
         let mut accent_phrases = accent_phrases;
         let (_, phoneme_data_list) = SynthesisEngine::initial_process(&accent_phrases);
 
@@ -292,6 +374,7 @@ impl SynthesisEngine {
         accent_phrases: Vec<AccentPhraseModel>,
         speaker_id: i64,
     ) -> Result<Vec<AccentPhraseModel>, String> {
+        check_accent_phrases(&accent_phrases)?;
         let mut accent_phrases = accent_phrases;
         let (_, phoneme_data_list) = SynthesisEngine::initial_process(&accent_phrases);
 
@@ -403,6 +486,53 @@ impl SynthesisEngine {
         speaker_id: i64,
         enable_interrogative_upspeak: bool,
     ) -> Result<Vec<f32>, String> {
+        let (wave, _) =
+            self.synthesis_with_timings(query, speaker_id, enable_interrogative_upspeak)?;
+        Ok(wave)
+    }
+
+    /// 音声と、音声中で各音素・各モーラが発音される時刻を返す
+    pub fn synthesis_with_timings(
+        &self,
+        query: AudioQueryModel,
+        speaker_id: i64,
+        enable_interrogative_upspeak: bool,
+    ) -> Result<(Vec<f32>, Timings), String> {
+        let mut features = SynthesisEngine::frame_features(query, enable_interrogative_upspeak)?;
+        let wave = self.decode(&mut features.f0, &mut features.phoneme, speaker_id)?;
+        Ok((wave, features.timings))
+    }
+
+    /// ポーズで区切った区間ごとに音声を返すイテレータを作る
+    pub fn synthesis_stream(
+        &self,
+        query: AudioQueryModel,
+        speaker_id: i64,
+        enable_interrogative_upspeak: bool,
+    ) -> Result<SynthesisStream<'_>, String> {
+        SynthesisStream::new(self, query, speaker_id, enable_interrogative_upspeak)
+    }
+
+    /// 音声合成モデルにフレームごとの音高と音素を与えて音声を得る
+    pub(crate) fn decode(
+        &self,
+        f0: &mut [f32],
+        phoneme: &mut [f32],
+        speaker_id: i64,
+    ) -> Result<Vec<f32>, String> {
+        self.core
+            .decode_forward(OjtPhoneme::num_phoneme(), f0, phoneme, speaker_id)
+            .ok_or_else(|| self.core.last_error_message())
+    }
+
+    /// クエリから音声合成モデルに与えるフレームごとの特徴量を作る
+    ///
+    /// 値の範囲は調べないので、範囲の検証が必要なら先に [`AudioQueryModel::validate`] を呼ぶ。
+    pub(crate) fn frame_features(
+        query: AudioQueryModel,
+        enable_interrogative_upspeak: bool,
+    ) -> Result<FrameFeatures, String> {
+        query.validate_structure()?;
         let AudioQueryModel {
             mut accent_phrases,
             speed_scale,
@@ -410,9 +540,12 @@ impl SynthesisEngine {
             intonation_scale,
             pre_phoneme_length,
             post_phoneme_length,
+            output_sampling_rate,
             ..
         } = query;
 
+        // 時刻は足したモーラをアクセント句の外に置くため、足す前のアクセント句から求める
+        let base_accent_phrases = accent_phrases.clone();
         if enable_interrogative_upspeak {
             accent_phrases = adjust_interrogative_accent_phrases(&accent_phrases);
         }
@@ -425,7 +558,7 @@ impl SynthesisEngine {
         let mut mean_f0 = 0.0;
         let mut count = 0;
 
-        for mora in flatten_moras {
+        for mora in flatten_moras.iter() {
             let MoraModel {
                 consonant,
                 consonant_length,
@@ -436,7 +569,7 @@ impl SynthesisEngine {
             if consonant.is_some() {
                 phoneme_length_list.push(consonant_length.unwrap());
             }
-            phoneme_length_list.push(vowel_length);
+            phoneme_length_list.push(*vowel_length);
             let f0_single = pitch * 2.0_f32.powf(pitch_scale);
             f0_list.push(f0_single);
             let big_than_zero = f0_single > 0.0;
@@ -471,13 +604,14 @@ impl SynthesisEngine {
 
         let mut phoneme: Vec<Vec<f32>> = Vec::new();
         let mut f0: Vec<f32> = Vec::new();
-        const RATE: f32 = 24000.0 / 256.0;
+        let mut phoneme_frames = Vec::new();
         let mut phoneme_length_sum = 0;
         let mut f0_count = 0;
         let mut vowel_indexes_index = 0;
         for i in 0..phoneme_length_list.len() {
             let phoneme_length =
-                ((phoneme_length_list[i] * RATE).round() / speed_scale).round() as usize;
+                ((phoneme_length_list[i] * FRAME_RATE).round() / speed_scale).round() as usize;
+            phoneme_frames.push(phoneme_length);
             let phoneme_id = phoneme_data_list[i].phoneme_id();
             for _ in 0..phoneme_length {
                 let mut phonemes_vector = vec![0.0; OjtPhoneme::num_phoneme()];
@@ -500,16 +634,20 @@ impl SynthesisEngine {
             flatten_phoneme.append(&mut p);
         }
 
-        if let Some(wave) = self.core.decode_forward(
-            OjtPhoneme::num_phoneme(),
-            &mut f0,
-            &mut flatten_phoneme,
-            speaker_id,
-        ) {
-            Ok(wave)
-        } else {
-            Err(self.core.last_error_message())
-        }
+        let timings = Timings::new(
+            &base_accent_phrases,
+            enable_interrogative_upspeak,
+            &phoneme_data_list,
+            &phoneme_frames,
+            output_sampling_rate,
+        );
+        Ok(FrameFeatures {
+            f0,
+            phoneme: flatten_phoneme,
+            phoneme_data_list,
+            phoneme_frames,
+            timings,
+        })
     }
 
     pub fn synthesis_wave_format(
@@ -518,99 +656,17 @@ impl SynthesisEngine {
         speaker_id: i64,
         enable_interrogative_upspeak: bool,
     ) -> Result<Vec<u8>, String> {
-        let wave = self.synthesis(query.clone(), speaker_id, enable_interrogative_upspeak)?;
-
-        let AudioQueryModel {
-            volume_scale,
-            output_sampling_rate,
-            output_stereo,
-            ..
-        } = query;
-
-        let num_channels: u8 = if output_stereo { 2 } else { 1 };
-        let bit_depth: u8 = 16;
-        let repeat_count: u32 =
-            (output_sampling_rate / DEFAULT_SAMPLING_RATE) * num_channels as u32;
-        let block_size: u8 = bit_depth * num_channels / 8;
-
-        let buf: Vec<u8> = Vec::new();
-        let mut cur = Cursor::new(buf);
-        cur.write("RIFF".as_bytes()).unwrap();
-        let mut bytes_size = wave.len() as i32 * repeat_count as i32 * 8;
-        let mut wave_size = bytes_size + 44 - 8;
-        for _ in 0..4 {
-            cur.write(&[(wave_size & 0xff) as u8]).unwrap(); // chunk size
-            wave_size >>= 8;
-        }
-        cur.write("WAVEfmt ".as_bytes()).unwrap();
-
-        cur.write(&[
-            16,           // fmt header length
-            0,            // fmt header length
-            0,            // fmt header length
-            0,            // fmt header length
-            1,            // linear PCM
-            0,            // linear PCM
-            num_channels, // channel
-            0,            // channel
-        ])
-        .unwrap();
-
-        let mut sampling_rate = output_sampling_rate as i32;
-        for _ in 0..4 {
-            cur.write(&[(sampling_rate & 0xff) as u8]).unwrap();
-            sampling_rate >>= 8;
-        }
-        let mut block_rate = (output_sampling_rate * block_size as u32) as i32;
-        for _ in 0..4 {
-            cur.write(&[(block_rate & 0xff) as u8]).unwrap();
-            block_rate >>= 8;
-        }
-
-        cur.write(&[block_size, 0, bit_depth, 0]).unwrap();
-
-        cur.write("data".as_bytes()).unwrap();
-        let data_p = cur.position();
-        for _ in 0..4 {
-            cur.write(&[(bytes_size & 0xff) as u8]).unwrap();
-            bytes_size >>= 8;
-        }
-
-        for value in wave {
-            let mut v = value * volume_scale;
-            // clip
-            if v > 1.0 {
-                v = 1.0;
-            }
-            if v < -1.0 {
-                v = -1.0;
-            }
-            let data = (v * 0x7fff as f32) as i16;
-            for _ in 0..repeat_count {
-                let fst = (data & 0xff) as u8;
-                #[allow(overflowing_literals)]
-                let snd = ((data & 0xff00) >> 8) as u8;
-                cur.write(&[fst, snd]).unwrap();
-            }
-        }
-
-        let mut last_p = cur.position();
-        let last_p_tmp = last_p;
-        last_p -= 8;
-        cur.seek(SeekFrom::Start(4)).unwrap();
-        for _ in 0..4 {
-            cur.write(&[(last_p & 0xff) as u8]).unwrap();
-            last_p >>= 8;
-        }
-        last_p = last_p_tmp;
-        cur.seek(SeekFrom::Start(data_p)).unwrap();
-        let mut pointer = last_p - data_p - 4;
-        for _ in 0..4 {
-            cur.write(&[(pointer & 0xff) as u8]).unwrap();
-            pointer >>= 8;
-        }
+        let (wave, timings) =
+            self.synthesis_with_timings(query.clone(), speaker_id, enable_interrogative_upspeak)?;
+        TtsResult::new(wave, query, timings)?
+            .audio
+            .to_wav(SampleFormat::Pcm16)
+            .map_err(|e| e.to_string())
+    }
 
-        Ok(cur.into_inner())
+    /// 音声合成モデルの話者とスタイルの情報（JSON）
+    pub fn metas(&self) -> String {
+        self.core.metas()
     }
 
     pub fn finalize(&self) {
@@ -0,0 +1,472 @@
+//! 音声合成のオプションと結果
+
+use std::ops::Range;
+
+use crate::{
+    acoustic_feature_extractor::OjtPhoneme,
+    audio::{AudioBuffer, TimedSpan},
+    encoder::OutputFormat,
+    frontend::Word,
+    loudness::LoudnessOptions,
+    model::{AccentPhraseModel, AudioQueryModel, MoraModel},
+    postprocess::PostProcess,
+    resampler::resample,
+    synthesis_engine::{interrogative_mora, DEFAULT_SAMPLING_RATE, FRAME_RATE},
+};
+
+/// [`VVTTSEngine::tts_with_options`](crate::VVTTSEngine::tts_with_options) のオプション
+///
+/// 各値の意味と範囲は [`AudioQueryModel`] と [`audio_query`](crate::audio_query) を参照。
+#[derive(Debug, Clone, PartialEq)]
+pub struct TtsOptions {
+    pub speed_scale: f32,
+    pub pitch_scale: f32,
+    pub intonation_scale: f32,
+    pub volume_scale: f32,
+    pub pre_phoneme_length: f32,
+    pub post_phoneme_length: f32,
+    pub output_sampling_rate: u32,
+    pub output_stereo: bool,
+    /// 疑問文の末尾の音高を上げる
+    pub enable_interrogative_upspeak: bool,
+    /// [`VVTTSEngine::tts_encoded`](crate::VVTTSEngine::tts_encoded) で出力する形式
+    pub output_format: OutputFormat,
+    /// 合成した音声のラウドネスを正規化する。息継ぎごとの合成では指定できない
+    pub loudness: Option<LoudnessOptions>,
+    /// ラウドネスの正規化の前にかける後処理。息継ぎごとの合成では指定できない
+    pub post_process: Vec<PostProcess>,
+}
+
+impl Default for TtsOptions {
+    fn default() -> Self {
+        TtsOptions {
+            speed_scale: 1.0,
+            pitch_scale: 0.0,
+            intonation_scale: 1.0,
+            volume_scale: 1.0,
+            pre_phoneme_length: 0.1,
+            post_phoneme_length: 0.1,
+            output_sampling_rate: DEFAULT_SAMPLING_RATE,
+            output_stereo: false,
+            enable_interrogative_upspeak: true,
+            output_format: OutputFormat::default(),
+            loudness: None,
+            post_process: Vec::new(),
+        }
+    }
+}
+
+impl TtsOptions {
+    /// クエリの合成パラメータをオプションの値で置き換える
+    pub fn apply(&self, query: &mut AudioQueryModel) {
+        query.speed_scale = self.speed_scale;
+        query.pitch_scale = self.pitch_scale;
+        query.intonation_scale = self.intonation_scale;
+        query.volume_scale = self.volume_scale;
+        query.pre_phoneme_length = self.pre_phoneme_length;
+        query.post_phoneme_length = self.post_phoneme_length;
+        query.output_sampling_rate = self.output_sampling_rate;
+        query.output_stereo = self.output_stereo;
+    }
+
+    /// 合成し終えた音声に後処理をかけ、ラウドネスを正規化する
+    pub fn finish<S: TimedSpan>(&self, audio: &mut AudioBuffer<S>) {
+        audio.post_process(&self.post_process);
+        if let Some(ref loudness) = self.loudness {
+            audio.normalize_loudness(loudness);
+        }
+    }
+}
+
+/// 音素の発音される時刻。前後の無音とポーズは `pau` になる
+///
+/// 時刻は秒で、サンプルの位置は出力のサンプリングレートでの 1 チャンネルあたりの位置。
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhonemeTiming {
+    pub phoneme: String,
+    pub start: f32,
+    pub end: f32,
+    pub start_sample: usize,
+    pub end_sample: usize,
+}
+
+/// モーラの発音される時刻。ポーズのモーラも含む
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoraTiming {
+    pub text: String,
+    pub start: f32,
+    pub end: f32,
+    pub start_sample: usize,
+    pub end_sample: usize,
+}
+
+/// アクセント句の発音される時刻。後ろのポーズと、疑問文の末尾の音高を上げるために足したモーラは含まない
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccentPhraseTiming {
+    pub text: String,
+    pub accent: u32,
+    /// [`Timings::moras`] の中でのこのアクセント句のモーラの範囲
+    pub moras: Range<usize>,
+    pub start: f32,
+    pub end: f32,
+    pub start_sample: usize,
+    pub end_sample: usize,
+}
+
+/// 単語の発音される時刻。読みの無い記号は含まない
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordTiming {
+    pub text: String,
+    pub start: f32,
+    pub end: f32,
+    pub start_sample: usize,
+    pub end_sample: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    pub phonemes: Vec<PhonemeTiming>,
+    pub moras: Vec<MoraTiming>,
+    pub accent_phrases: Vec<AccentPhraseTiming>,
+    /// 日本語処理部でテキストから合成した場合だけ入る。読みを書き込んだテキスト、SSML、
+    /// AquesTalk 風記法などからの合成では空になる
+    pub words: Vec<WordTiming>,
+    pub sampling_rate: u32,
+}
+
+impl Timings {
+    /// 音声合成で各音素に割り当てたフレーム数から時刻を求める
+    ///
+    /// `phonemes` は前後の無音を含む音素の列で、`accent_phrases` のモーラはその間の音素に対応する。
+    /// `enable_interrogative_upspeak` の場合は、疑問文のアクセント句の後ろに音高を上げるモーラがあるものとし、
+    /// ポーズのモーラと同じくアクセント句の外に置く。
+    /// 速度の反映と丸めは音声合成と同じく `frames` で済んでいる。
+    pub(crate) fn new(
+        accent_phrases: &[AccentPhraseModel],
+        enable_interrogative_upspeak: bool,
+        phonemes: &[OjtPhoneme],
+        frames: &[usize],
+        sampling_rate: u32,
+    ) -> Timings {
+        let mut frame_starts = vec![0];
+        for frame in frames {
+            frame_starts.push(frame_starts.last().unwrap() + frame);
+        }
+        let seconds = |frame: usize| frame as f32 / FRAME_RATE;
+        let sample = |frame: usize| (seconds(frame) * sampling_rate as f32).round() as usize;
+
+        let phoneme_timings = phonemes
+            .iter()
+            .enumerate()
+            .map(|(i, phoneme)| PhonemeTiming {
+                phoneme: phoneme.phoneme.clone(),
+                start: seconds(frame_starts[i]),
+                end: seconds(frame_starts[i + 1]),
+                start_sample: sample(frame_starts[i]),
+                end_sample: sample(frame_starts[i + 1]),
+            })
+            .collect();
+
+        let mut mora_timings = Vec::new();
+        let mut accent_phrase_timings = Vec::new();
+        // 最初の音素は前の無音
+        let mut index = 1;
+        let mut push_mora = |mora: &MoraModel, mora_timings: &mut Vec<MoraTiming>| {
+            let start = index;
+            if mora.consonant.is_some() {
+                index += 1;
+            }
+            index += 1;
+            mora_timings.push(MoraTiming {
+                text: mora.text.clone(),
+                start: seconds(frame_starts[start]),
+                end: seconds(frame_starts[index]),
+                start_sample: sample(frame_starts[start]),
+                end_sample: sample(frame_starts[index]),
+            });
+        };
+        for accent_phrase in accent_phrases {
+            let first = mora_timings.len();
+            for mora in accent_phrase.moras.iter() {
+                push_mora(mora, &mut mora_timings);
+            }
+            let moras = first..mora_timings.len();
+            if !moras.is_empty() {
+                let (first, last) = (&mora_timings[moras.start], &mora_timings[moras.end - 1]);
+                accent_phrase_timings.push(AccentPhraseTiming {
+                    text: mora_timings[moras.clone()]
+                        .iter()
+                        .map(|mora| mora.text.as_str())
+                        .collect(),
+                    accent: accent_phrase.accent,
+                    start: first.start,
+                    end: last.end,
+                    start_sample: first.start_sample,
+                    end_sample: last.end_sample,
+                    moras,
+                });
+            }
+            if enable_interrogative_upspeak {
+                if let Some(interrogative_mora) = interrogative_mora(accent_phrase) {
+                    push_mora(&interrogative_mora, &mut mora_timings);
+                }
+            }
+            if let Some(ref pause_mora) = accent_phrase.pause_mora {
+                push_mora(pause_mora, &mut mora_timings);
+            }
+        }
+
+        Timings {
+            phonemes: phoneme_timings,
+            moras: mora_timings,
+            accent_phrases: accent_phrase_timings,
+            words: Vec::new(),
+            sampling_rate,
+        }
+    }
+
+    /// 単語をアクセント句のモーラに先頭から順に割り当てる
+    ///
+    /// 単語が無い場合は単語の時刻を空にする。単語のモーラ数の合計はアクセント句のモーラ数と等しくなければならない。
+    pub(crate) fn set_words(&mut self, words: &[Word]) -> Result<(), String> {
+        self.words.clear();
+        if words.is_empty() {
+            return Ok(());
+        }
+        let moras = self
+            .accent_phrases
+            .iter()
+            .flat_map(|accent_phrase| accent_phrase.moras.clone())
+            .collect::<Vec<_>>();
+        let mora_count = words.iter().map(|word| word.mora_count).sum::<usize>();
+        if mora_count != moras.len() {
+            return Err(format!(
+                "words have {} moras, but accent phrases have {}",
+                mora_count,
+                moras.len()
+            ));
+        }
+        let mut index = 0;
+        self.words = words
+            .iter()
+            .filter(|word| word.mora_count > 0)
+            .map(|word| {
+                let first = &self.moras[moras[index]];
+                let last = &self.moras[moras[index + word.mora_count - 1]];
+                index += word.mora_count;
+                WordTiming {
+                    text: word.text.clone(),
+                    start: first.start,
+                    end: last.end,
+                    start_sample: first.start_sample,
+                    end_sample: last.end_sample,
+                }
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// 先頭から `removed` サンプルを取り除いた音声での位置にする。`length` は取り除いた後の長さ
+    pub(crate) fn shift(&mut self, removed: usize, length: usize) {
+        self.map_samples(|sample| sample.saturating_sub(removed).min(length));
+    }
+
+    /// `timings` を `offset` サンプル後ろにずらして後ろに足す
+    pub(crate) fn append(&mut self, mut timings: Timings, offset: usize) {
+        timings.sampling_rate = self.sampling_rate;
+        timings.map_samples(|sample| sample + offset);
+        let moras = self.moras.len();
+        for timing in timings.accent_phrases.iter_mut() {
+            timing.moras = timing.moras.start + moras..timing.moras.end + moras;
+        }
+        self.phonemes.append(&mut timings.phonemes);
+        self.moras.append(&mut timings.moras);
+        self.accent_phrases.append(&mut timings.accent_phrases);
+        self.words.append(&mut timings.words);
+    }
+
+    // サンプルの位置を変え、時刻をそれに合わせる
+    fn map_samples<F: Fn(usize) -> usize>(&mut self, f: F) {
+        let sampling_rate = self.sampling_rate as f32;
+        let map = |start_sample: &mut usize, end_sample: &mut usize| {
+            *start_sample = f(*start_sample);
+            *end_sample = f(*end_sample);
+            (
+                *start_sample as f32 / sampling_rate,
+                *end_sample as f32 / sampling_rate,
+            )
+        };
+        for timing in self.phonemes.iter_mut() {
+            (timing.start, timing.end) = map(&mut timing.start_sample, &mut timing.end_sample);
+        }
+        for timing in self.moras.iter_mut() {
+            (timing.start, timing.end) = map(&mut timing.start_sample, &mut timing.end_sample);
+        }
+        for timing in self.accent_phrases.iter_mut() {
+            (timing.start, timing.end) = map(&mut timing.start_sample, &mut timing.end_sample);
+        }
+        for timing in self.words.iter_mut() {
+            (timing.start, timing.end) = map(&mut timing.start_sample, &mut timing.end_sample);
+        }
+    }
+}
+
+/// 音声合成の結果
+#[derive(Debug, Clone)]
+pub struct TtsResult {
+    /// 一続きに合成した音声なので区間は持たず、つなぎ目が無いため
+    /// [`PostProcess::Declick`] は何もしない
+    pub audio: AudioBuffer,
+    /// 合成に使ったクエリ
+    pub audio_query: AudioQueryModel,
+}
+
+impl TtsResult {
+    /// 音声合成モデルの出力を、クエリで指定した音量・サンプリングレート・チャンネル数にする
+    pub(crate) fn new(
+        wave: Vec<f32>,
+        audio_query: AudioQueryModel,
+        timings: Timings,
+    ) -> Result<TtsResult, String> {
+        let wave = if audio_query.output_sampling_rate == DEFAULT_SAMPLING_RATE {
+            wave
+        } else {
+            resample(
+                &wave,
+                DEFAULT_SAMPLING_RATE,
+                audio_query.output_sampling_rate,
+            )?
+        };
+        let channels: u16 = if audio_query.output_stereo { 2 } else { 1 };
+        let samples = wave
+            .iter()
+            .flat_map(|value| {
+                std::iter::repeat_n(value * audio_query.volume_scale, channels as usize)
+            })
+            .collect();
+        let mut audio = AudioBuffer::new(samples, audio_query.output_sampling_rate, channels);
+        audio.timings = timings;
+        Ok(TtsResult { audio, audio_query })
+    }
+
+    /// 音素・モーラ・アクセント句・単語の時刻
+    pub fn timings(&self) -> &Timings {
+        &self.audio.timings
+    }
+}
+
+#[cfg(test)]
+mod tts_tests {
+    use super::Timings;
+    use crate::{
+        direct_input::accent_phrases_from_kana,
+        frontend::Word,
+        model::AudioQueryModel,
+        synthesis_engine::{to_phoneme_data_list, SynthesisEngine, FRAME_RATE},
+    };
+
+    fn word(text: &str, mora_count: usize) -> Word {
+        Word {
+            text: text.to_string(),
+            mora_count,
+        }
+    }
+
+    #[test]
+    fn test_timings() {
+        let accent_phrases = accent_phrases_from_kana("カア、ン").unwrap();
+        let phonemes = to_phoneme_data_list(
+            ["pau", "k", "a", "a", "pau", "N", "pau"]
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+        );
+        let mut timings = Timings::new(
+            &accent_phrases,
+            true,
+            &phonemes,
+            &[10, 3, 5, 6, 20, 7, 10],
+            48000,
+        );
+        assert_eq!(timings.phonemes.len(), 7);
+        assert_eq!(timings.phonemes[1].phoneme, "k");
+        assert_eq!(timings.phonemes[1].start, 10.0 / FRAME_RATE);
+        let moras = timings
+            .moras
+            .iter()
+            .map(|mora| {
+                (
+                    mora.text.as_str(),
+                    (mora.start * FRAME_RATE).round() as usize,
+                    (mora.end * FRAME_RATE).round() as usize,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            moras,
+            [
+                ("カ", 10, 18),
+                ("ア", 18, 24),
+                ("、", 24, 44),
+                ("ン", 44, 51)
+            ]
+        );
+        // 1 フレームは 24 kHz で 256 サンプル
+        assert_eq!(timings.moras[3].start_sample, 44 * 512);
+
+        assert_eq!(timings.accent_phrases.len(), 2);
+        assert_eq!(timings.accent_phrases[0].text, "カア");
+        assert_eq!(timings.accent_phrases[0].moras, 0..2);
+        assert_eq!(timings.accent_phrases[1].moras, 3..4);
+        assert_eq!(
+            (
+                timings.accent_phrases[1].start_sample,
+                timings.accent_phrases[1].end_sample
+            ),
+            (44 * 512, 51 * 512)
+        );
+
+        assert!(timings
+            .set_words(&[word("カア", 2), word("ン", 2)])
+            .is_err());
+        timings
+            .set_words(&[word("カ", 1), word("ア", 1), word("、", 0), word("ン", 1)])
+            .unwrap();
+        let words = timings
+            .words
+            .iter()
+            .map(|word| (word.text.as_str(), word.start_sample / 512))
+            .collect::<Vec<_>>();
+        assert_eq!(words, [("カ", 10), ("ア", 18), ("ン", 44)]);
+
+        let mut appended = timings.clone();
+        appended.append(timings.clone(), 100 * 512);
+        assert_eq!(appended.moras.len(), 8);
+        assert_eq!(appended.accent_phrases[3].moras, 7..8);
+        assert_eq!(appended.words[5].start_sample, 144 * 512);
+        assert_eq!(appended.words[5].start, 144.0 / FRAME_RATE);
+
+        timings.shift(10 * 512, 41 * 512);
+        assert_eq!(timings.words[2].start_sample, 34 * 512);
+        assert_eq!(timings.phonemes[0].end, 0.0);
+    }
+
+    #[test]
+    fn test_words_with_interrogative_upspeak() {
+        let mut accent_phrases = accent_phrases_from_kana("ホ'ント？").unwrap();
+        for mora in accent_phrases[0].moras.iter_mut() {
+            mora.pitch = 5.5;
+            mora.vowel_length = 0.1;
+        }
+        let query = AudioQueryModel::builder(accent_phrases).build().unwrap();
+        let mut timings = SynthesisEngine::frame_features(query, true)
+            .unwrap()
+            .timings;
+        // 音高を上げるために足したモーラはアクセント句と単語に含めない
+        assert_eq!(timings.moras.len(), 4);
+        assert_eq!(timings.moras[3].text, "オ");
+        assert_eq!(timings.accent_phrases[0].moras, 0..3);
+        timings.set_words(&[word("ホント", 3)]).unwrap();
+        assert_eq!(timings.words[0].end_sample, timings.moras[2].end_sample);
+    }
+}